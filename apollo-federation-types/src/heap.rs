@@ -0,0 +1,219 @@
+use serde::Serialize;
+use std::fmt;
+
+// The heap limit V8 starts with when nothing else is configured.
+const DEFAULT_INITIAL_LIMIT_MB: usize = 256;
+
+const MEGABYTE: usize = 1024 * 1024;
+
+/// How the V8 heap of a JavaScript runtime is allowed to grow.
+///
+/// This is shared by the runtimes of composition and of the query planner.
+/// Each time the heap is about to run out of memory, its limit is multiplied by `growth_factor`,
+/// up to `max_limit_mb`.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapPolicy {
+    /// The heap limit the runtime starts with, in megabytes
+    pub initial_limit_mb: usize,
+    /// The factor the heap limit is multiplied by when the heap is about to run out of memory.
+    /// It must be greater than 1.
+    pub growth_factor: f64,
+    /// The heap limit is never raised above this many megabytes. There is no ceiling if `None`,
+    /// in which case we rely on the execution environment (OS) to provide one.
+    pub max_limit_mb: Option<usize>,
+    /// What to do when the heap runs out of memory and its limit is already at `max_limit_mb`
+    pub on_max_limit: HeapLimitAction,
+}
+
+/// What to do when a heap reaches the ceiling of its [`HeapPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeapLimitAction {
+    /// Interrupt what the runtime was running, a composition or a planning request, and fail it.
+    ///
+    /// The heap is given some headroom to let it unwind.
+    Fail,
+    /// Let V8 abort the process, which is what happens when there is no near heap limit callback.
+    Abort,
+}
+
+impl Default for HeapPolicy {
+    /// The heap starts with a 256MB limit, and grows by 1.25x without a ceiling.
+    fn default() -> Self {
+        Self {
+            initial_limit_mb: DEFAULT_INITIAL_LIMIT_MB,
+            growth_factor: 1.25,
+            max_limit_mb: None,
+            on_max_limit: HeapLimitAction::Fail,
+        }
+    }
+}
+
+// The growth factor is compared bitwise, so that `HeapPolicy` can be part of a hashable configuration.
+impl PartialEq for HeapPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        self.initial_limit_mb == other.initial_limit_mb
+            && self.growth_factor.to_bits() == other.growth_factor.to_bits()
+            && self.max_limit_mb == other.max_limit_mb
+            && self.on_max_limit == other.on_max_limit
+    }
+}
+
+impl Eq for HeapPolicy {}
+
+impl std::hash::Hash for HeapPolicy {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.initial_limit_mb.hash(state);
+        self.growth_factor.to_bits().hash(state);
+        self.max_limit_mb.hash(state);
+        self.on_max_limit.hash(state);
+    }
+}
+
+/// The heap limits a near heap limit callback can pick from, in bytes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum NextHeapLimit {
+    /// The heap can grow to this limit
+    Grow(usize),
+    /// The heap reached its ceiling
+    Exceeded,
+}
+
+/// Why a [`HeapPolicy`] can't be used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidHeapPolicy(String);
+
+impl fmt::Display for InvalidHeapPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid heap policy: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidHeapPolicy {}
+
+impl HeapPolicy {
+    /// Check the policy can grow the heap, and that its ceiling is above its initial limit.
+    pub fn validate(&self) -> Result<(), InvalidHeapPolicy> {
+        // NaN fails this check too
+        if !(self.growth_factor > 1.0 && self.growth_factor.is_finite()) {
+            return Err(InvalidHeapPolicy(format!(
+                "the growth factor must be a finite number greater than 1, got {}",
+                self.growth_factor
+            )));
+        }
+        if self.initial_limit_mb == 0 {
+            return Err(InvalidHeapPolicy(
+                "the initial limit must be at least 1MB".to_string(),
+            ));
+        }
+        match self.max_limit_mb {
+            Some(max_limit_mb) if max_limit_mb < self.initial_limit_mb => {
+                Err(InvalidHeapPolicy(format!(
+                    "the ceiling of {max_limit_mb}MB is below the initial limit of {}MB",
+                    self.initial_limit_mb
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The heap limit the runtime starts with, in bytes
+    pub fn initial_limit(&self) -> usize {
+        self.initial_limit_mb * MEGABYTE
+    }
+
+    /// The ceiling of the heap, in bytes
+    pub fn max_limit(&self) -> Option<usize> {
+        self.max_limit_mb
+            .map(|max_limit_mb| max_limit_mb * MEGABYTE)
+    }
+
+    /// The limit a heap that is about to run out of `current_limit` bytes may grow to.
+    pub fn next_limit(&self, current_limit: usize) -> NextHeapLimit {
+        let grown = (current_limit as f64 * self.growth_factor) as usize;
+        let next = match self.max_limit() {
+            Some(max_limit) => grown.min(max_limit),
+            None => grown,
+        };
+
+        if next > current_limit {
+            NextHeapLimit::Grow(next)
+        } else {
+            NextHeapLimit::Exceeded
+        }
+    }
+}
+
+/// A snapshot of the V8 heap of a JavaScript runtime.
+///
+/// Sizes are in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeapStatistics {
+    /// The size of the live objects in the heap
+    pub used_heap_size: usize,
+    /// The size the heap currently reserved
+    pub total_heap_size: usize,
+    /// The size the heap can grow to before the heap limit is reached
+    pub heap_size_limit: usize,
+    /// The memory allocated outside of the heap, but retained by JavaScript objects
+    pub external_memory: usize,
+    /// The number of times the heap limit was raised so far, because the heap was about to run out of memory
+    pub heap_expansions: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_grows_up_to_the_ceiling() {
+        let policy = HeapPolicy {
+            initial_limit_mb: 100,
+            growth_factor: 1.5,
+            max_limit_mb: Some(200),
+            on_max_limit: HeapLimitAction::Fail,
+        };
+
+        assert_eq!(
+            NextHeapLimit::Grow(150 * MEGABYTE),
+            policy.next_limit(policy.initial_limit())
+        );
+        assert_eq!(
+            NextHeapLimit::Grow(200 * MEGABYTE),
+            policy.next_limit(150 * MEGABYTE)
+        );
+        assert_eq!(NextHeapLimit::Exceeded, policy.next_limit(200 * MEGABYTE));
+    }
+
+    #[test]
+    fn heap_grows_without_ceiling() {
+        let policy = HeapPolicy {
+            initial_limit_mb: 100,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            NextHeapLimit::Grow(125 * MEGABYTE),
+            policy.next_limit(policy.initial_limit())
+        );
+    }
+
+    #[test]
+    fn policies_that_cant_grow_are_invalid() {
+        assert!(HeapPolicy::default().validate().is_ok());
+        for growth_factor in [1.0, 0.5, -2.0, f64::NAN, f64::INFINITY] {
+            let policy = HeapPolicy {
+                growth_factor,
+                ..Default::default()
+            };
+            assert!(policy.validate().is_err(), "{growth_factor} is accepted");
+        }
+
+        let policy = HeapPolicy {
+            initial_limit_mb: 200,
+            max_limit_mb: Some(100),
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+}
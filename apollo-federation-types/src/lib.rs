@@ -7,4 +7,7 @@ pub mod build_plugin;
 #[cfg(feature = "config")]
 pub mod config;
 
+#[cfg(feature = "heap")]
+pub mod heap;
+
 pub(crate) type UncaughtJson = std::collections::BTreeMap<String, serde_json::Value>;
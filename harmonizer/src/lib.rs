@@ -32,6 +32,8 @@ composition implementation while we work toward something else.
 #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
 use deno_core::Snapshot;
 use deno_core::{JsRuntime, RuntimeOptions};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 mod js_types;
 
@@ -40,11 +42,76 @@ use js_types::CompositionError;
 use apollo_federation_types::build::{
     BuildError, BuildErrors, BuildOutput, BuildResult, SubgraphDefinition,
 };
+use apollo_federation_types::heap::NextHeapLimit;
+pub use apollo_federation_types::heap::{HeapLimitAction, HeapPolicy, HeapStatistics};
 
-// A reasonable default starting limit for our deno heap.
-const APOLLO_HARMONIZER_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE_DEFAULT: &str = "256";
+// A reasonable default starting size for our deno heap.
+const APOLLO_HARMONIZER_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE_DEFAULT: usize = 256;
 // A reasonable default max limit for our deno heap.
-const APOLLO_HARMONIZER_EXPERIMENTAL_V8_MAX_HEAP_SIZE_DEFAULT: &str = "1400";
+const APOLLO_HARMONIZER_EXPERIMENTAL_V8_MAX_HEAP_SIZE_DEFAULT: usize = 1400;
+
+const MEGABYTE: usize = 1024 * 1024;
+
+/// Options for [`harmonize_with_options`].
+#[derive(Debug, Clone)]
+pub struct HarmonizerOptions {
+    /// Limits the number of schema nodes returned with composition errors, to prevent OOM issues
+    pub nodes_limit: Option<u32>,
+    /// How the heap of the JavaScript runtime is allowed to grow
+    pub heap_policy: HeapPolicy,
+}
+
+impl Default for HarmonizerOptions {
+    fn default() -> Self {
+        Self {
+            nodes_limit: None,
+            heap_policy: default_heap_policy(),
+        }
+    }
+}
+
+/// The heap policy of composition, unless another one is provided.
+///
+/// The initial limit defaults to 1400MB, and grows by 1.25x without a ceiling.
+/// If the `APOLLO_HARMONIZER_EXPERIMENTAL_V8_MAX_HEAP_SIZE` environment variable is set,
+/// the heap starts with this limit and doesn't grow past it.
+pub fn default_heap_policy() -> HeapPolicy {
+    let max_limit_mb = std::env::var("APOLLO_HARMONIZER_EXPERIMENTAL_V8_MAX_HEAP_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok());
+
+    HeapPolicy {
+        initial_limit_mb: max_limit_mb
+            .unwrap_or(APOLLO_HARMONIZER_EXPERIMENTAL_V8_MAX_HEAP_SIZE_DEFAULT),
+        max_limit_mb,
+        ..Default::default()
+    }
+}
+
+/// Take a snapshot of the V8 heap used by a composition.
+fn heap_statistics(runtime: &mut JsRuntime, heap_expansions: usize) -> HeapStatistics {
+    let mut statistics = deno_core::v8::HeapStatistics::default();
+    runtime.v8_isolate().get_heap_statistics(&mut statistics);
+
+    HeapStatistics {
+        used_heap_size: statistics.used_heap_size(),
+        total_heap_size: statistics.total_heap_size(),
+        heap_size_limit: statistics.heap_size_limit(),
+        external_memory: statistics.external_memory(),
+        heap_expansions,
+    }
+}
+
+/// The outcome of [`harmonize_with_options`].
+#[derive(Debug)]
+pub struct HarmonizerOutput {
+    /// The composed supergraph, or the composition errors
+    pub result: BuildResult,
+    /// Statistics about the V8 heap of this composition.
+    ///
+    /// They are taken once composition completes, so they show how close it came to the heap limit.
+    pub heap_statistics: HeapStatistics,
+}
 
 /// The `harmonize` function receives a [`Vec<SubgraphDefinition>`] and invokes JavaScript
 /// composition on it, either returning the successful output, or a list of error messages.
@@ -59,35 +126,54 @@ pub fn harmonize_limit(
     subgraph_definitions: Vec<SubgraphDefinition>,
     nodes_limit: Option<u32>,
 ) -> BuildResult {
-    let initial_heap_size = std::env::var("APOLLO_HARMONIZER_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE")
-        .unwrap_or_else(|_e| {
-            APOLLO_HARMONIZER_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE_DEFAULT.to_string()
-        });
+    harmonize_with_options(
+        subgraph_definitions,
+        HarmonizerOptions {
+            nodes_limit,
+            ..Default::default()
+        },
+    )
+    .result
+}
 
-    let max_heap_size_maybe = std::env::var("APOLLO_HARMONIZER_EXPERIMENTAL_V8_MAX_HEAP_SIZE").ok();
-    let max_heap_size_provided = max_heap_size_maybe.is_some();
-    let max_heap_size = max_heap_size_maybe
-        .unwrap_or_else(|| APOLLO_HARMONIZER_EXPERIMENTAL_V8_MAX_HEAP_SIZE_DEFAULT.to_string());
-
-    // The first flag is argv[0], so provide an ignorable value
-    let flags = vec![
-        "--ignored".to_string(),
-        "--initial_heap_size".to_string(),
-        initial_heap_size.to_string(),
-        "--max-heap-size".to_string(),
-        max_heap_size.to_string(),
-    ];
-
-    // Deno will warn us if we supply flags it doesn't recognise.
-    // We ignore "--ignored" and report any others as warnings
-    let ignored: Vec<_> = deno_core::v8_set_flags(flags)
-        .into_iter()
-        .filter(|x| x != "--ignored")
-        .collect();
-    if !ignored.is_empty() {
-        panic!("deno ignored these flags: {:?}", ignored);
+/// The `harmonize` function receives a [`Vec<SubgraphDefinition>`] and invokes JavaScript
+/// composition on it, either returning the successful output, or a list of error messages.
+/// The composition runtime is configured with [`HarmonizerOptions`], and reports its heap statistics.
+pub fn harmonize_with_options(
+    subgraph_definitions: Vec<SubgraphDefinition>,
+    options: HarmonizerOptions,
+) -> HarmonizerOutput {
+    let HarmonizerOptions {
+        nodes_limit,
+        heap_policy,
+    } = options;
+
+    if let Err(e) = heap_policy.validate() {
+        let mut errors = BuildErrors::new();
+        errors.push(BuildError::composition_error(
+            Some("INVALID_HEAP_POLICY".to_string()),
+            Some(e.to_string()),
+            None,
+            None,
+        ));
+        return HarmonizerOutput {
+            result: Err(errors),
+            heap_statistics: Default::default(),
+        };
     }
 
+    let initial_heap_size = std::env::var("APOLLO_HARMONIZER_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(APOLLO_HARMONIZER_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE_DEFAULT);
+
+    // The heap limits are set on the isolate rather than with V8 flags,
+    // which are global to the process.
+    let create_params = deno_core::v8::CreateParams::default().heap_limits(
+        initial_heap_size.min(heap_policy.initial_limit_mb) * MEGABYTE,
+        heap_policy.initial_limit(),
+    );
+
     // The snapshot is created in the build_harmonizer.rs script and included in our binary image
     #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
     let buffer = include_bytes!(concat!(env!("OUT_DIR"), "/composition.snap"));
@@ -95,12 +181,14 @@ pub fn harmonize_limit(
     #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
     let mut runtime = JsRuntime::new(RuntimeOptions {
         startup_snapshot: Some(Snapshot::Static(buffer)),
+        create_params: Some(create_params),
         ..Default::default()
     });
 
     #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
     let mut runtime = {
         let mut runtime = JsRuntime::new(RuntimeOptions {
+            create_params: Some(create_params),
             ..Default::default()
         });
 
@@ -119,24 +207,52 @@ pub fn harmonize_limit(
         runtime
     };
 
-    // if max_heap_size was not set, we resize the heap every time
-    // we approach the limit. This is a tradeoff as it might cause
-    // an instance to run out of physical memory.
-    if !max_heap_size_provided {
-        // Add a callback that expands our heap by 1.25 each time
-        // it is invoked. There is no limit, since we rely on the
-        // execution environment (OS) to provide that.
+    let heap_expansions: Arc<AtomicUsize> = Default::default();
+
+    let heap_limit_reached: Arc<AtomicBool> = Default::default();
+
+    // Add a callback that expands our heap according to the heap policy.
+    // Without a ceiling, the heap keeps growing until the execution environment (OS)
+    // runs out of memory. Once it reaches the ceiling of the policy, composition is
+    // either failed or aborted by V8, depending on `on_max_limit`.
+    {
         let name = "harmonize".to_string();
+        let heap_expansions = heap_expansions.clone();
+        let heap_limit_reached = heap_limit_reached.clone();
+        let isolate = runtime.v8_isolate().thread_safe_handle();
         runtime.add_near_heap_limit_callback(move |current, initial| {
-            let new = current * 5 / 4;
-            tracing::info!(
-                "deno heap expansion({}): initial: {}, current: {}, new: {}",
-                name,
-                initial,
-                current,
-                new
-            );
-            new
+            match (heap_policy.next_limit(current), heap_policy.on_max_limit) {
+                (NextHeapLimit::Grow(new), _) => {
+                    heap_expansions.fetch_add(1, Ordering::Relaxed);
+                    tracing::info!(
+                        "deno heap expansion({}): initial: {}, current: {}, new: {}",
+                        name,
+                        initial,
+                        current,
+                        new
+                    );
+                    new
+                }
+                (NextHeapLimit::Exceeded, HeapLimitAction::Fail) => {
+                    tracing::error!(
+                        "deno heap limit reached({}): current: {}, interrupting composition",
+                        name,
+                        current
+                    );
+                    heap_limit_reached.store(true, Ordering::SeqCst);
+                    isolate.terminate_execution();
+                    // Composition needs some memory to unwind
+                    current + current / 4
+                }
+                (NextHeapLimit::Exceeded, HeapLimitAction::Abort) => {
+                    tracing::error!(
+                        "deno heap limit reached({}): current: {}, aborting",
+                        name,
+                        current
+                    );
+                    current
+                }
+            }
         });
     }
 
@@ -172,10 +288,13 @@ pub fn harmonize_limit(
         .expect("unable to evaluate nodes limit in JavaScript runtime");
 
     // run the unmodified do_compose.js file, which expects `serviceList` to be set
-    match runtime.execute_script(
+    let composition = runtime.execute_script(
         "do_compose",
         deno_core::FastString::Static(include_str!("../bundled/do_compose.js")),
-    ) {
+    );
+    let heap_statistics = heap_statistics(&mut runtime, heap_expansions.load(Ordering::Relaxed));
+
+    let result = match composition {
         Ok(execute_result) => {
             let scope = &mut runtime.handle_scope();
             let local = deno_core::v8::Local::new(scope, execute_result);
@@ -202,6 +321,21 @@ pub fn harmonize_limit(
                 }
             }
         }
+        Err(_) if heap_limit_reached.load(Ordering::SeqCst) => {
+            let mut errors = BuildErrors::new();
+            errors.push(BuildError::composition_error(
+                Some("HEAP_LIMIT_EXCEEDED".to_string()),
+                Some(format!(
+                    "Composition ran out of memory: the JavaScript heap reached its limit of {}MB",
+                    heap_policy
+                        .max_limit_mb
+                        .unwrap_or(heap_policy.initial_limit_mb)
+                )),
+                None,
+                None,
+            ));
+            Err(errors)
+        }
         Err(e) => {
             let mut errors = BuildErrors::new();
             errors.push(BuildError::composition_error(
@@ -215,6 +349,11 @@ pub fn harmonize_limit(
             ));
             Err(errors)
         }
+    };
+
+    HarmonizerOutput {
+        result,
+        heap_statistics,
     }
 }
 
@@ -264,4 +403,23 @@ mod tests {
             .supergraph_sdl
         );
     }
+
+    #[test]
+    fn heap_statistics_are_recorded() {
+        use crate::{harmonize_with_options, SubgraphDefinition};
+
+        let output = harmonize_with_options(
+            vec![SubgraphDefinition::new(
+                "users",
+                "undefined",
+                "type Query { users: [ID!] }",
+            )],
+            Default::default(),
+        );
+        output.result.unwrap();
+
+        let statistics = output.heap_statistics;
+        assert!(statistics.used_heap_size > 0);
+        assert!(statistics.used_heap_size <= statistics.heap_size_limit);
+    }
 }
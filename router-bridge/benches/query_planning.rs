@@ -1,6 +1,7 @@
 use criterion::criterion_group;
 use criterion::criterion_main;
 use criterion::Criterion;
+use router_bridge::plan_types::QueryPlanResult;
 use router_bridge::planner::PlanOptions;
 use router_bridge::planner::Planner;
 use router_bridge::planner::QueryPlannerConfig;
@@ -13,7 +14,7 @@ fn from_elem(c: &mut Criterion) {
         let runtime = tokio::runtime::Runtime::new().unwrap();
 
         let planner = runtime.block_on(async {
-            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+            Planner::<QueryPlanResult>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
                 .await
                 .unwrap()
         });
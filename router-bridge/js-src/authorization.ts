@@ -0,0 +1,211 @@
+import {
+  DirectiveNode,
+  DocumentNode,
+  FragmentDefinitionNode,
+  getDirectiveValues,
+  getNamedType,
+  GraphQLDirective,
+  GraphQLNamedType,
+  GraphQLSchema,
+  isAbstractType,
+  isInterfaceType,
+  isObjectType,
+  Kind,
+  OperationDefinitionNode,
+  SelectionSetNode,
+} from "graphql";
+
+// The identities of the specs that define the authorization directives.
+export const AUTHENTICATED_SPEC_IDENTITY: string =
+  "https://specs.apollo.dev/authenticated";
+export const REQUIRES_SCOPES_SPEC_IDENTITY: string =
+  "https://specs.apollo.dev/requiresScopes";
+export const POLICY_SPEC_IDENTITY: string = "https://specs.apollo.dev/policy";
+
+export interface Requirement {
+  // The schema coordinate of the type or field carrying the directive.
+  coordinate: string;
+  // The requirement is met if every entry of one of the lists is met.
+  anyOf: string[][];
+}
+
+export interface AuthorizationRequirements {
+  // The schema coordinates that require the request to be authenticated.
+  authenticated: string[];
+  scopes: Requirement[];
+  policies: Requirement[];
+}
+
+export interface AuthorizationDirectiveNames {
+  authenticated?: string;
+  requiresScopes?: string;
+  policy?: string;
+}
+
+type HasDirectives = { readonly directives?: ReadonlyArray<DirectiveNode> };
+
+// Collects the `@authenticated`, `@requiresScopes` and `@policy` requirements
+// of the fields an operation selects, of the types they return, and of the
+// types of its fragments.
+//
+// A selection on an interface or a union may resolve to any of its possible
+// types, so it carries the requirements of those types and of their fields.
+//
+// Every selection is visited, whatever its @skip or @include, so the
+// requirements are those of the operation in the worst case.
+export class Authorization {
+  private readonly authenticated?: GraphQLDirective;
+  private readonly requiresScopes?: GraphQLDirective;
+  private readonly policy?: GraphQLDirective;
+
+  constructor(
+    private readonly schema: GraphQLSchema,
+    names: AuthorizationDirectiveNames
+  ) {
+    const directive = (name?: string) =>
+      name != null ? schema.getDirective(name) ?? undefined : undefined;
+    this.authenticated = directive(names.authenticated);
+    this.requiresScopes = directive(names.requiresScopes);
+    this.policy = directive(names.policy);
+  }
+
+  // `document` must have been validated, and hold an operation named `operationName`,
+  // or a single operation.
+  requirements(
+    document: DocumentNode,
+    operationName: string | undefined
+  ): AuthorizationRequirements {
+    const operations = document.definitions.filter(
+      (definition): definition is OperationDefinitionNode =>
+        definition.kind === Kind.OPERATION_DEFINITION
+    );
+    const operation =
+      operations.find(
+        (operation) => operation.name?.value === operationName
+      ) ?? operations[0];
+    const fragments = new Map<string, FragmentDefinitionNode>();
+    for (const definition of document.definitions) {
+      if (definition.kind === Kind.FRAGMENT_DEFINITION) {
+        fragments.set(definition.name.value, definition);
+      }
+    }
+
+    const requirements: AuthorizationRequirements = {
+      authenticated: [],
+      scopes: [],
+      policies: [],
+    };
+    const seen = new Set<string>();
+    const collect = (coordinate: string, node?: HasDirectives | null) => {
+      // The same type or field can be selected many times, but is only reported once
+      if (node == null || seen.has(coordinate)) {
+        return;
+      }
+      seen.add(coordinate);
+
+      if (
+        this.authenticated != null &&
+        getDirectiveValues(this.authenticated, node) != null
+      ) {
+        requirements.authenticated.push(coordinate);
+      }
+      if (this.requiresScopes != null) {
+        const scopes = getDirectiveValues(this.requiresScopes, node)?.scopes;
+        if (scopes != null) {
+          requirements.scopes.push({
+            coordinate,
+            anyOf: scopes as string[][],
+          });
+        }
+      }
+      if (this.policy != null) {
+        const policies = getDirectiveValues(this.policy, node)?.policies;
+        if (policies != null) {
+          requirements.policies.push({
+            coordinate,
+            anyOf: policies as string[][],
+          });
+        }
+      }
+    };
+    const collectType = (type?: GraphQLNamedType | null) => {
+      if (type == null) {
+        return;
+      }
+      collect(type.name, type.astNode);
+      if (isAbstractType(type)) {
+        for (const possibleType of this.schema.getPossibleTypes(type)) {
+          collect(possibleType.name, possibleType.astNode);
+        }
+      }
+    };
+
+    // Fragments only need to be visited once, as requirements are not repeated
+    const visitedFragments = new Set<string>();
+    const visit = (type: GraphQLNamedType, selectionSet: SelectionSetNode) => {
+      for (const selection of selectionSet.selections) {
+        switch (selection.kind) {
+          case Kind.FIELD: {
+            if (!isObjectType(type) && !isInterfaceType(type)) {
+              // only `__typename` can be selected on unions
+              break;
+            }
+            const field = type.getFields()[selection.name.value];
+            if (field == null) {
+              // introspection fields
+              break;
+            }
+            const fieldType = getNamedType(field.type);
+            collect(`${type.name}.${field.name}`, field.astNode);
+            if (isInterfaceType(type)) {
+              for (const possibleType of this.schema.getPossibleTypes(type)) {
+                collect(
+                  `${possibleType.name}.${field.name}`,
+                  possibleType.getFields()[field.name]?.astNode
+                );
+              }
+            }
+            collectType(fieldType);
+            if (selection.selectionSet != null) {
+              visit(fieldType, selection.selectionSet);
+            }
+            break;
+          }
+          case Kind.INLINE_FRAGMENT: {
+            const fragmentType =
+              selection.typeCondition != null
+                ? this.schema.getType(selection.typeCondition.name.value)
+                : type;
+            if (fragmentType != null) {
+              collectType(fragmentType);
+              visit(fragmentType, selection.selectionSet);
+            }
+            break;
+          }
+          case Kind.FRAGMENT_SPREAD: {
+            const name = selection.name.value;
+            const fragment = fragments.get(name);
+            if (fragment == null || visitedFragments.has(name)) {
+              break;
+            }
+            visitedFragments.add(name);
+            const fragmentType = this.schema.getType(
+              fragment.typeCondition.name.value
+            );
+            if (fragmentType != null) {
+              collectType(fragmentType);
+              visit(fragmentType, fragment.selectionSet);
+            }
+            break;
+          }
+        }
+      }
+    };
+
+    const rootType = this.schema.getRootType(operation.operation);
+    collectType(rootType);
+    visit(rootType, operation.selectionSet);
+
+    return requirements;
+  }
+}
@@ -0,0 +1,347 @@
+import {
+  DirectiveNode,
+  DocumentNode,
+  FieldNode,
+  FragmentDefinitionNode,
+  getArgumentValues,
+  getDirectiveValues,
+  getNamedType,
+  GraphQLCompositeType,
+  GraphQLDirective,
+  GraphQLField,
+  GraphQLInputType,
+  GraphQLSchema,
+  isCompositeType,
+  isInputObjectType,
+  isListType,
+  isNonNullType,
+  isObjectType,
+  isInterfaceType,
+  Kind,
+  OperationDefinitionNode,
+  SelectionSetNode,
+} from "graphql";
+
+// The identity of the demand control spec, which defines `@cost` and `@listSize`.
+export const COST_SPEC_IDENTITY: string = "https://specs.apollo.dev/cost";
+
+// Every mutation is assumed to cost at least this much, whatever it selects.
+const MUTATION_COST: number = 10;
+
+export interface DemandCostOptions {
+  // The size of the lists that have no `@listSize` to tell theirs.
+  defaultListSize: number;
+}
+
+export interface FieldCost {
+  // The response path of the field, without list indexes.
+  path: string[];
+  // The schema coordinate of the field, like `Query.products`.
+  coordinate: string;
+  // The cost of the field, its arguments and its selections.
+  cost: number;
+}
+
+export interface DemandCost {
+  cost: number;
+  fields: FieldCost[];
+}
+
+type Variables = Record<string, unknown>;
+
+type HasDirectives = { readonly directives?: ReadonlyArray<DirectiveNode> };
+
+interface ListSize {
+  assumedSize?: number;
+  slicingArguments?: string[];
+  sizedFields?: string[];
+}
+
+// Estimates the cost of operations from the `@cost` and `@listSize`
+// directives of a supergraph, following the IBM GraphQL cost specification:
+// - a field costs the weight of its `@cost`, or else the weight of its type,
+//   which defaults to 1 for composite types and 0 for leaf types,
+// - the arguments (and input fields) with a `@cost` that are provided add
+//   their weight,
+// - a list multiplies the cost of its items by its size, which comes from the
+//   slicing arguments or the assumed size of its `@listSize`, or else from
+//   `defaultListSize`.
+//
+// @skip and @include are ignored, so every selection is assumed to be executed.
+export class DemandControl {
+  private readonly costDirective?: GraphQLDirective;
+  private readonly listSizeDirective?: GraphQLDirective;
+
+  constructor(
+    private readonly schema: GraphQLSchema,
+    costDirectiveName?: string,
+    listSizeDirectiveName?: string
+  ) {
+    this.costDirective =
+      costDirectiveName != null
+        ? schema.getDirective(costDirectiveName) ?? undefined
+        : undefined;
+    this.listSizeDirective =
+      listSizeDirectiveName != null
+        ? schema.getDirective(listSizeDirectiveName) ?? undefined
+        : undefined;
+  }
+
+  // `document` must have been validated, and hold an operation named `operationName`,
+  // or a single operation.
+  estimate(
+    document: DocumentNode,
+    operationName: string | undefined,
+    variables: Variables,
+    options: DemandCostOptions
+  ): DemandCost {
+    const operations = document.definitions.filter(
+      (definition): definition is OperationDefinitionNode =>
+        definition.kind === Kind.OPERATION_DEFINITION
+    );
+    const operation =
+      operations.find(
+        (operation) => operation.name?.value === operationName
+      ) ?? operations[0];
+    const fragments = new Map<string, FragmentDefinitionNode>();
+    for (const definition of document.definitions) {
+      if (definition.kind === Kind.FRAGMENT_DEFINITION) {
+        fragments.set(definition.name.value, definition);
+      }
+    }
+
+    const estimation = new Estimation(
+      this,
+      fragments,
+      variables,
+      options.defaultListSize
+    );
+    const rootType = this.schema.getRootType(operation.operation);
+    let cost = operation.operation === "mutation" ? MUTATION_COST : 0;
+    cost += estimation.selectionSetCost(
+      rootType,
+      operation.selectionSet,
+      [],
+      new Map()
+    );
+
+    return { cost, fields: estimation.fields };
+  }
+
+  getType(name: string): GraphQLCompositeType | undefined {
+    const type = this.schema.getType(name);
+    return type != null && isCompositeType(type) ? type : undefined;
+  }
+
+  weight(node?: HasDirectives | null): number | undefined {
+    if (this.costDirective == null || node == null) {
+      return undefined;
+    }
+    const weight = getDirectiveValues(this.costDirective, node)?.weight;
+    return typeof weight === "number" ? weight : undefined;
+  }
+
+  listSize(node?: HasDirectives | null): ListSize | undefined {
+    if (this.listSizeDirective == null || node == null) {
+      return undefined;
+    }
+    return getDirectiveValues(this.listSizeDirective, node) as
+      | ListSize
+      | undefined;
+  }
+}
+
+// The state of the estimation of a single operation.
+class Estimation {
+  readonly fields: FieldCost[] = [];
+  // The fragments being estimated, to stop at fragment cycles
+  private readonly visiting = new Set<string>();
+
+  constructor(
+    private readonly demandControl: DemandControl,
+    private readonly fragments: Map<string, FragmentDefinitionNode>,
+    private readonly variables: Variables,
+    private readonly defaultListSize: number
+  ) {}
+
+  // `sizedFields` holds the sizes of the lists that are sized by the `@listSize`
+  // of the field that selects this selection set.
+  selectionSetCost(
+    parentType: GraphQLCompositeType,
+    selectionSet: SelectionSetNode,
+    path: string[],
+    sizedFields: Map<string, number>
+  ): number {
+    let cost = 0;
+    for (const selection of selectionSet.selections) {
+      switch (selection.kind) {
+        case Kind.FIELD: {
+          if (!isObjectType(parentType) && !isInterfaceType(parentType)) {
+            // only `__typename` can be selected on unions
+            break;
+          }
+          const field = parentType.getFields()[selection.name.value];
+          if (field == null) {
+            // introspection fields
+            break;
+          }
+          cost += this.fieldCost(
+            parentType.name,
+            field,
+            selection,
+            [...path, selection.alias?.value ?? selection.name.value],
+            sizedFields.get(selection.name.value)
+          );
+          break;
+        }
+        case Kind.INLINE_FRAGMENT: {
+          const type =
+            selection.typeCondition != null
+              ? this.demandControl.getType(selection.typeCondition.name.value)
+              : parentType;
+          if (type != null) {
+            cost += this.selectionSetCost(
+              type,
+              selection.selectionSet,
+              path,
+              sizedFields
+            );
+          }
+          break;
+        }
+        case Kind.FRAGMENT_SPREAD: {
+          const name = selection.name.value;
+          const fragment = this.fragments.get(name);
+          // Fragment cycles are reported by validation, which may be disabled.
+          if (fragment == null || this.visiting.has(name)) {
+            break;
+          }
+          const type = this.demandControl.getType(
+            fragment.typeCondition.name.value
+          );
+          if (type != null) {
+            this.visiting.add(name);
+            cost += this.selectionSetCost(
+              type,
+              fragment.selectionSet,
+              path,
+              sizedFields
+            );
+            this.visiting.delete(name);
+          }
+          break;
+        }
+      }
+    }
+    return cost;
+  }
+
+  private fieldCost(
+    parentTypeName: string,
+    field: GraphQLField<unknown, unknown>,
+    node: FieldNode,
+    path: string[],
+    sizedByParent?: number
+  ): number {
+    // The cost is filled in once the selections are estimated, so that fields are listed in document order.
+    const fieldCost: FieldCost = {
+      path,
+      coordinate: `${parentTypeName}.${field.name}`,
+      cost: 0,
+    };
+    this.fields.push(fieldCost);
+
+    const namedType = getNamedType(field.type);
+    const typeCost =
+      this.demandControl.weight(field.astNode) ??
+      this.demandControl.weight(namedType.astNode) ??
+      (isCompositeType(namedType) ? 1 : 0);
+
+    let args: Record<string, unknown> = {};
+    try {
+      args = getArgumentValues(field, node, this.variables);
+    } catch (_) {
+      // the variables don't match the arguments, so we can't tell which arguments are set
+    }
+
+    let argumentsCost = 0;
+    for (const argument of field.args) {
+      if (args[argument.name] !== undefined) {
+        argumentsCost +=
+          (this.demandControl.weight(argument.astNode) ?? 0) +
+          this.inputCost(argument.type, args[argument.name]);
+      }
+    }
+
+    const listSize = this.demandControl.listSize(field.astNode);
+    const size = this.listSizeOf(listSize, args);
+    const sizedFields = new Map<string, number>(
+      (listSize?.sizedFields ?? []).map((name) => [name, size])
+    );
+
+    let selectionsCost = 0;
+    if (node.selectionSet != null && isCompositeType(namedType)) {
+      selectionsCost = this.selectionSetCost(
+        namedType,
+        node.selectionSet,
+        path,
+        sizedFields
+      );
+    }
+
+    let multiplier = 1;
+    if (isListType(isNonNullType(field.type) ? field.type.ofType : field.type)) {
+      if (sizedByParent != null) {
+        multiplier = sizedByParent;
+      } else if (listSize != null && sizedFields.size === 0) {
+        multiplier = size;
+      } else {
+        multiplier = this.defaultListSize;
+      }
+    }
+
+    fieldCost.cost = argumentsCost + multiplier * (typeCost + selectionsCost);
+    return fieldCost.cost;
+  }
+
+  // The largest slicing argument that is set, or else the assumed size.
+  private listSizeOf(
+    listSize: ListSize | undefined,
+    args: Record<string, unknown>
+  ): number {
+    const slices = (listSize?.slicingArguments ?? [])
+      .map((name) => args[name])
+      .filter((value): value is number => typeof value === "number");
+    if (slices.length > 0) {
+      return Math.max(...slices);
+    }
+    return listSize?.assumedSize ?? this.defaultListSize;
+  }
+
+  private inputCost(type: GraphQLInputType, value: unknown): number {
+    if (value == null) {
+      return 0;
+    }
+    if (Array.isArray(value)) {
+      return value.reduce(
+        (cost: number, item) => cost + this.inputCost(type, item),
+        0
+      );
+    }
+    const namedType = getNamedType(type);
+    if (!isInputObjectType(namedType) || typeof value !== "object") {
+      return 0;
+    }
+
+    let cost = 0;
+    for (const inputField of Object.values(namedType.getFields())) {
+      const fieldValue = (value as Record<string, unknown>)[inputField.name];
+      if (fieldValue !== undefined) {
+        cost +=
+          (this.demandControl.weight(inputField.astNode) ?? 0) +
+          this.inputCost(inputField.type, fieldValue);
+      }
+    }
+    return cost;
+  }
+}
@@ -0,0 +1,176 @@
+import {
+  ArgumentNode,
+  DocumentNode,
+  FragmentDefinitionNode,
+  getOperationAST,
+  InlineFragmentNode,
+  Kind,
+  ObjectFieldNode,
+  OperationDefinitionNode,
+  print,
+  SelectionNode,
+  SelectionSetNode,
+  stripIgnoredCharacters,
+  ValueNode,
+  VariableDefinitionNode,
+} from "graphql";
+
+// Returns an executable document holding only the operation named
+// `operationName` (or the only operation), with its fragment spreads
+// replaced by inline fragments, and its selections, arguments, input object
+// fields and variable definitions sorted, printed without ignored characters.
+//
+// Two documents that only differ by the order of those, by their fragments
+// or by their whitespace and comments have the same normalized form.
+//
+// `document` must have been validated.
+export function normalizeOperation(
+  document: DocumentNode,
+  operationName?: string
+): string {
+  const operation = getOperationAST(document, operationName);
+  const fragments = new Map<string, FragmentDefinitionNode>();
+  for (const definition of document.definitions) {
+    if (definition.kind === Kind.FRAGMENT_DEFINITION) {
+      fragments.set(definition.name.value, definition);
+    }
+  }
+
+  const normalized: OperationDefinitionNode = {
+    ...operation,
+    variableDefinitions: sortBy(
+      (operation.variableDefinitions ?? []).map(normalizeVariableDefinition),
+      (definition) => definition.variable.name.value
+    ),
+    directives: operation.directives?.map(normalizeDirective),
+    selectionSet: normalizeSelectionSet(operation.selectionSet, fragments),
+  };
+
+  return stripIgnoredCharacters(
+    print({ kind: Kind.DOCUMENT, definitions: [normalized] })
+  );
+}
+
+function normalizeSelectionSet(
+  selectionSet: SelectionSetNode,
+  fragments: Map<string, FragmentDefinitionNode>
+): SelectionSetNode {
+  const selections = selectionSet.selections.map(
+    (selection): SelectionNode => {
+      switch (selection.kind) {
+        case Kind.FIELD:
+          return {
+            ...selection,
+            arguments: normalizeArguments(selection.arguments),
+            directives: selection.directives?.map(normalizeDirective),
+            selectionSet:
+              selection.selectionSet != null
+                ? normalizeSelectionSet(selection.selectionSet, fragments)
+                : undefined,
+          };
+        case Kind.INLINE_FRAGMENT:
+          return {
+            ...selection,
+            directives: selection.directives?.map(normalizeDirective),
+            selectionSet: normalizeSelectionSet(
+              selection.selectionSet,
+              fragments
+            ),
+          };
+        case Kind.FRAGMENT_SPREAD: {
+          const fragment = fragments.get(selection.name.value);
+          const inlined: InlineFragmentNode = {
+            kind: Kind.INLINE_FRAGMENT,
+            typeCondition: fragment.typeCondition,
+            directives: [
+              ...(fragment.directives ?? []),
+              ...(selection.directives ?? []),
+            ].map(normalizeDirective),
+            selectionSet: normalizeSelectionSet(
+              fragment.selectionSet,
+              fragments
+            ),
+          };
+          return inlined;
+        }
+      }
+    }
+  );
+
+  // Fields come first, by response name, then inline fragments. Selections with
+  // the same key are ordered by their printed form, so that the order is total.
+  return {
+    ...selectionSet,
+    selections: sortBy(selections, (selection) => {
+      const printed = print(selection);
+      switch (selection.kind) {
+        case Kind.FIELD:
+          return `0 ${selection.alias?.value ?? selection.name.value} ${printed}`;
+        default:
+          return `1 ${printed}`;
+      }
+    }),
+  };
+}
+
+function normalizeVariableDefinition(
+  definition: VariableDefinitionNode
+): VariableDefinitionNode {
+  return {
+    ...definition,
+    defaultValue:
+      definition.defaultValue != null
+        ? (normalizeValue(
+            definition.defaultValue
+          ) as VariableDefinitionNode["defaultValue"])
+        : undefined,
+    directives: definition.directives?.map(normalizeDirective),
+  };
+}
+
+function normalizeDirective<
+  T extends { readonly arguments?: ReadonlyArray<ArgumentNode> }
+>(directive: T): T {
+  return { ...directive, arguments: normalizeArguments(directive.arguments) };
+}
+
+function normalizeArguments(
+  args?: ReadonlyArray<ArgumentNode>
+): ArgumentNode[] {
+  return sortBy(
+    (args ?? []).map((argument) => ({
+      ...argument,
+      value: normalizeValue(argument.value),
+    })),
+    (argument) => argument.name.value
+  );
+}
+
+function normalizeValue(value: ValueNode): ValueNode {
+  switch (value.kind) {
+    case Kind.LIST:
+      return { ...value, values: value.values.map(normalizeValue) };
+    case Kind.OBJECT:
+      return {
+        ...value,
+        fields: sortBy(
+          value.fields.map(
+            (field): ObjectFieldNode => ({
+              ...field,
+              value: normalizeValue(field.value),
+            })
+          ),
+          (field) => field.name.value
+        ),
+      };
+    default:
+      return value;
+  }
+}
+
+function sortBy<T>(items: ReadonlyArray<T>, key: (item: T) => string): T[] {
+  return items
+    .map((item) => ({ item, key: key(item) }))
+    .sort((a, b) => (a.key < b.key ? -1 : a.key > b.key ? 1 : 0))
+    .map(({ item }) => item);
+}
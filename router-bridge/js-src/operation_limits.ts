@@ -0,0 +1,174 @@
+import {
+  DocumentNode,
+  FragmentDefinitionNode,
+  GraphQLError,
+  Kind,
+  Lexer,
+  OperationDefinitionNode,
+  SelectionSetNode,
+  Source,
+  TokenKind,
+} from "graphql";
+import { OperationLimits } from "./types";
+
+export const MAX_DEPTH_LIMIT_EXT_CODE: string = "MAX_DEPTH_LIMIT";
+export const MAX_ALIASES_LIMIT_EXT_CODE: string = "MAX_ALIASES_LIMIT";
+export const MAX_ROOT_FIELDS_LIMIT_EXT_CODE: string = "MAX_ROOT_FIELDS_LIMIT";
+export const MAX_TOKENS_LIMIT_EXT_CODE: string = "MAX_TOKENS_LIMIT";
+
+// Returns an error if the operation string holds more than `maxTokens` tokens.
+// Lexing stops as soon as the limit is exceeded, and syntax errors are left
+// for the parser to report.
+export function checkTokenLimit(
+  operationString: string,
+  limits?: OperationLimits
+): GraphQLError | undefined {
+  const maxTokens = limits?.maxTokens;
+  if (maxTokens == null) {
+    return undefined;
+  }
+
+  const lexer = new Lexer(new Source(operationString));
+  let tokens = 0;
+  try {
+    while (lexer.advance().kind !== TokenKind.EOF) {
+      tokens += 1;
+      if (tokens > maxTokens) {
+        return limitError(
+          `Document contains more than ${maxTokens} tokens`,
+          MAX_TOKENS_LIMIT_EXT_CODE
+        );
+      }
+    }
+  } catch (_) {
+    return undefined;
+  }
+  return undefined;
+}
+
+interface SelectionSetShape {
+  // the nesting of fields below the selection set
+  depth: number;
+  // the aliased fields below the selection set
+  aliases: number;
+  // the fields of the selection set itself
+  fields: number;
+}
+
+// Returns the errors for the limits exceeded by the operation that will be
+// planned. Fragment spreads are expanded, and each fragment is only measured
+// once so that documents that spread fragments many times stay cheap to check.
+export function checkOperationLimits(
+  document: DocumentNode,
+  operationName?: string,
+  limits?: OperationLimits
+): GraphQLError[] {
+  if (
+    limits == null ||
+    (limits.maxDepth == null &&
+      limits.maxAliases == null &&
+      limits.maxRootFields == null)
+  ) {
+    return [];
+  }
+
+  const operations = document.definitions.filter(
+    (definition): definition is OperationDefinitionNode =>
+      definition.kind === Kind.OPERATION_DEFINITION
+  );
+  const operation =
+    operationName != null
+      ? operations.find((operation) => operation.name?.value === operationName)
+      : operations.length === 1
+      ? operations[0]
+      : undefined;
+  // Unknown or ambiguous operations are reported by the validation that follows.
+  if (operation == null) {
+    return [];
+  }
+
+  const fragments = new Map<string, FragmentDefinitionNode>();
+  for (const definition of document.definitions) {
+    if (definition.kind === Kind.FRAGMENT_DEFINITION) {
+      fragments.set(definition.name.value, definition);
+    }
+  }
+  const measured = new Map<string, SelectionSetShape>();
+  const visiting = new Set<string>();
+
+  const measure = (selectionSet: SelectionSetNode): SelectionSetShape => {
+    const shape = { depth: 0, aliases: 0, fields: 0 };
+    const add = (inner: SelectionSetShape) => {
+      shape.depth = Math.max(shape.depth, inner.depth);
+      shape.aliases += inner.aliases;
+      shape.fields += inner.fields;
+    };
+
+    for (const selection of selectionSet.selections) {
+      switch (selection.kind) {
+        case Kind.FIELD: {
+          const inner = selection.selectionSet
+            ? measure(selection.selectionSet)
+            : { depth: 0, aliases: 0, fields: 0 };
+          shape.depth = Math.max(shape.depth, inner.depth + 1);
+          shape.aliases += inner.aliases + (selection.alias ? 1 : 0);
+          shape.fields += 1;
+          break;
+        }
+        case Kind.INLINE_FRAGMENT:
+          add(measure(selection.selectionSet));
+          break;
+        case Kind.FRAGMENT_SPREAD: {
+          const name = selection.name.value;
+          const fragment = fragments.get(name);
+          // Unknown fragments and fragment cycles are reported by validation.
+          if (fragment == null || visiting.has(name)) {
+            break;
+          }
+          let fragmentShape = measured.get(name);
+          if (fragmentShape == null) {
+            visiting.add(name);
+            fragmentShape = measure(fragment.selectionSet);
+            visiting.delete(name);
+            measured.set(name, fragmentShape);
+          }
+          add(fragmentShape);
+          break;
+        }
+      }
+    }
+    return shape;
+  };
+
+  const shape = measure(operation.selectionSet);
+  const errors: GraphQLError[] = [];
+  if (limits.maxDepth != null && shape.depth > limits.maxDepth) {
+    errors.push(
+      limitError(
+        `Operation depth of ${shape.depth} exceeds the limit of ${limits.maxDepth}`,
+        MAX_DEPTH_LIMIT_EXT_CODE
+      )
+    );
+  }
+  if (limits.maxAliases != null && shape.aliases > limits.maxAliases) {
+    errors.push(
+      limitError(
+        `Operation has ${shape.aliases} aliases, which exceeds the limit of ${limits.maxAliases}`,
+        MAX_ALIASES_LIMIT_EXT_CODE
+      )
+    );
+  }
+  if (limits.maxRootFields != null && shape.fields > limits.maxRootFields) {
+    errors.push(
+      limitError(
+        `Operation has ${shape.fields} root fields, which exceeds the limit of ${limits.maxRootFields}`,
+        MAX_ROOT_FIELDS_LIMIT_EXT_CODE
+      )
+    );
+  }
+  return errors;
+}
+
+function limitError(message: string, code: string): GraphQLError {
+  return new GraphQLError(message, { extensions: { code } });
+}
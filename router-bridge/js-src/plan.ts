@@ -13,6 +13,7 @@ import {
   validate,
   printSchema,
   graphqlSync,
+  buildSchema,
 } from "graphql";
 
 import {
@@ -25,14 +26,32 @@ import {
   usageReportingSignature,
 } from "@apollo/utils.usagereporting";
 import { ReferencedFieldsForType } from "@apollo/usage-reporting-protobuf";
-import { QueryPlannerConfigExt } from "./types";
+import { OperationResult, QueryPlannerConfigExt } from "./types";
 import { ROUTER_SUPPORTED_SUPERGRAPH_FEATURES } from "./supported_features";
+import { checkOperationLimits, checkTokenLimit } from "./operation_limits";
+import {
+  COST_SPEC_IDENTITY,
+  DemandControl,
+  DemandCost,
+  DemandCostOptions,
+} from "./demand_control";
+import {
+  AUTHENTICATED_SPEC_IDENTITY,
+  Authorization,
+  AuthorizationRequirements,
+  POLICY_SPEC_IDENTITY,
+  REQUIRES_SCOPES_SPEC_IDENTITY,
+} from "./authorization";
+import { coerceVariables } from "./variables";
+import { normalizeOperation } from "./normalize";
+import { PhaseTimer, PlanningStatistics } from "./statistics";
 
 const PARSE_FAILURE: string = "## GraphQLParseFailure\n";
 const PARSE_FAILURE_EXT_CODE: string = "GRAPHQL_PARSE_FAILED";
 const VALIDATION_FAILURE: string = "## GraphQLValidationFailure\n";
 const VALIDATION_FAILURE_EXT_CODE: string = "GRAPHQL_VALIDATION_FAILED";
 const UNKNOWN_OPERATION: string = "## GraphQLUnknownOperationName\n";
+const COST_SPEC_NOT_LINKED_EXT_CODE: string = "COST_SPEC_NOT_LINKED";
 
 export type ReferencedFieldsByType = Record<string, ReferencedFieldsForType>;
 
@@ -44,6 +63,8 @@ export type UsageReporting = {
 export interface ExecutionResultWithUsageReporting<T>
   extends ExecutionResult<T> {
   usageReporting: UsageReporting;
+  // Only reported by `plan`, if the `planningStatistics` option is enabled
+  statistics?: PlanningStatistics;
 }
 
 export interface QueryPlanResult {
@@ -51,6 +72,21 @@ export interface QueryPlanResult {
   queryPlan: QueryPlan;
 }
 
+export interface ValidatedOperation {
+  operationName: string | null;
+  operationKind: string;
+}
+
+export interface NormalizedOperation {
+  document: string;
+}
+
+// An operation, along with the document it was parsed from.
+interface ParsedOperation {
+  operation: Operation;
+  document: DocumentNode;
+}
+
 export interface PlanOptions {
   // We receive these across the bridge as an array of strings,
   // but ultimately build a Map object out of it for use in the planner.
@@ -61,6 +97,10 @@ export class BridgeQueryPlanner {
   private readonly supergraph: Supergraph;
   private readonly apiSchema: GraphQLSchema;
   private readonly planner: QueryPlanner;
+  // Built on first use, as most planners never need them
+  private graphqlSupergraph?: GraphQLSchema;
+  private demandControl?: DemandControl;
+  private authorization?: Authorization;
 
   constructor(
     public readonly schemaString: string,
@@ -81,18 +121,23 @@ export class BridgeQueryPlanner {
     providedOperationName?: string,
     options?: PlanOptions
   ): ExecutionResultWithUsageReporting<QueryPlanResult> {
+    const timer = this.options.planningStatistics
+      ? new PhaseTimer()
+      : undefined;
     let operationResult = this.operation(
       operationString,
-      providedOperationName
+      providedOperationName,
+      timer
     );
     if (operationResult.errors != null) {
       return {
         usageReporting: operationResult.usageReporting,
         errors: operationResult.errors,
+        statistics: timer != null ? { phases: timer.phases } : undefined,
       };
     }
     let usageReporting = operationResult.usageReporting;
-    let operation = operationResult.data;
+    let operation = operationResult.data.operation;
     const operationName = operation?.name;
     const buildQueryPlanOptions = options
       ? {
@@ -106,6 +151,7 @@ export class BridgeQueryPlanner {
       operation,
       buildQueryPlanOptions
     );
+    timer?.end("queryPlanning");
     let formattedQueryPlan: string | null;
     try {
       formattedQueryPlan = prettyFormatQueryPlan(queryPlan);
@@ -121,6 +167,7 @@ export class BridgeQueryPlanner {
       );
       formattedQueryPlan = null;
     }
+    timer?.end("formatting");
 
     return {
       usageReporting,
@@ -128,17 +175,41 @@ export class BridgeQueryPlanner {
         queryPlan,
         formattedQueryPlan,
       },
+      statistics:
+        timer != null
+          ? {
+              phases: timer.phases,
+              evaluatedPlanCount:
+                this.planner.lastGeneratedPlanStatistics()?.evaluatedPlanCount,
+            }
+          : undefined,
     };
   }
 
   operation(
     operationString: string,
-    providedOperationName?: string
-  ): ExecutionResultWithUsageReporting<Operation> {
+    providedOperationName?: string,
+    timer?: PhaseTimer
+  ): ExecutionResultWithUsageReporting<ParsedOperation> {
     let document: DocumentNode;
 
+    const tokenLimitError = checkTokenLimit(
+      operationString,
+      this.options.operationLimits
+    );
+    if (tokenLimitError != null) {
+      return {
+        usageReporting: {
+          statsReportKey: VALIDATION_FAILURE,
+          referencedFieldsByType: {},
+        },
+        errors: [tokenLimitError],
+      };
+    }
+
     try {
       document = parse(operationString);
+      timer?.end("parsing");
     } catch (parseError) {
       // parse throws GraphQLError
       return {
@@ -157,12 +228,30 @@ export class BridgeQueryPlanner {
       };
     }
 
+    // The limits are checked before validation, which can be expensive on
+    // operations with many aliases.
+    const limitErrors = checkOperationLimits(
+      document,
+      providedOperationName,
+      this.options.operationLimits
+    );
+    if (limitErrors.length > 0) {
+      return {
+        usageReporting: {
+          statsReportKey: VALIDATION_FAILURE,
+          referencedFieldsByType: {},
+        },
+        errors: limitErrors,
+      };
+    }
+
     // Federation does some validation, but not all.  We need to do
     // all default validations that are provided by GraphQL.
     const validationErrors =
       this.options.graphqlValidation === false
         ? []
         : validate(this.apiSchema, document);
+    timer?.end("validation");
     if (validationErrors.length > 0) {
       return {
         usageReporting: {
@@ -196,6 +285,7 @@ export class BridgeQueryPlanner {
       operation = operationFromDocument(this.supergraph.schema, document, {
         operationName: providedOperationName,
       });
+      timer?.end("operationFromDocument");
     } catch (e) {
       // operationFromDocument throws GraphQLError
 
@@ -234,6 +324,7 @@ export class BridgeQueryPlanner {
       document,
       operationName,
     });
+    timer?.end("usageReporting");
 
     const statsReportKey = `# ${operationName || "-"}\n${
       operationDerivedData.signature
@@ -244,10 +335,186 @@ export class BridgeQueryPlanner {
         statsReportKey,
         referencedFieldsByType: operationDerivedData.referencedFieldsByType,
       },
-      data: operation,
+      data: { operation, document },
     };
   }
 
+  // Parses and validates the operation, then passes it to `f`.
+  // The usage reporting of the operation is returned along with the result
+  // of `f`, or the errors that prevented calling it.
+  private withOperation<T>(
+    operationString: string,
+    providedOperationName: string | undefined,
+    f: (operation: Operation, document: DocumentNode) => T
+  ): ExecutionResultWithUsageReporting<T> {
+    const operationResult = this.operation(
+      operationString,
+      providedOperationName
+    );
+    if (operationResult.errors != null) {
+      return {
+        usageReporting: operationResult.usageReporting,
+        errors: operationResult.errors,
+      };
+    }
+
+    const { operation, document } = operationResult.data;
+    return {
+      usageReporting: operationResult.usageReporting,
+      data: f(operation, document),
+    };
+  }
+
+  // Runs the same checks as `plan`, without building a query plan.
+  validate(
+    operationString: string,
+    providedOperationName?: string
+  ): ExecutionResultWithUsageReporting<ValidatedOperation> {
+    return this.withOperation(
+      operationString,
+      providedOperationName,
+      (operation) => ({
+        operationName: operation.name ?? null,
+        operationKind: operation.rootKind,
+      })
+    );
+  }
+
+  normalizedOperation(
+    operationString: string,
+    providedOperationName?: string
+  ): ExecutionResultWithUsageReporting<NormalizedOperation> {
+    return this.withOperation(
+      operationString,
+      providedOperationName,
+      (operation, document) => ({
+        document: normalizeOperation(document, operation.name),
+      })
+    );
+  }
+
+  coerceVariables(
+    operationString: string,
+    providedOperationName?: string,
+    variables?: Record<string, unknown>
+  ): ExecutionResultWithUsageReporting<OperationResult> {
+    return this.withOperation(
+      operationString,
+      providedOperationName,
+      (operation, document) =>
+        coerceVariables(
+          this.apiSchema,
+          document,
+          operation.name,
+          variables ?? {}
+        )
+    );
+  }
+
+  demandCost(
+    operationString: string,
+    providedOperationName?: string,
+    variables?: Record<string, unknown>,
+    options?: DemandCostOptions
+  ): ExecutionResultWithUsageReporting<DemandCost> {
+    // Without the cost spec, every estimate would silently use the default
+    // costs. The operation is still validated, so its errors come first.
+    if (this.directiveName(COST_SPEC_IDENTITY, "cost") == null) {
+      const { usageReporting, errors } = this.withOperation(
+        operationString,
+        providedOperationName,
+        () => undefined
+      );
+      return {
+        usageReporting,
+        errors: errors ?? [
+          new GraphQLError(
+            `the supergraph doesn't link the cost spec (${COST_SPEC_IDENTITY}), so the cost of operations can't be estimated`,
+            { extensions: { code: COST_SPEC_NOT_LINKED_EXT_CODE } }
+          ),
+        ],
+      };
+    }
+
+    return this.withOperation(
+      operationString,
+      providedOperationName,
+      (operation, document) =>
+        this.getDemandControl().estimate(
+          document,
+          operation.name,
+          variables ?? {},
+          options ?? { defaultListSize: 1 }
+        )
+    );
+  }
+
+  authorizationRequirements(
+    operationString: string,
+    providedOperationName?: string
+  ): ExecutionResultWithUsageReporting<AuthorizationRequirements> {
+    return this.withOperation(
+      operationString,
+      providedOperationName,
+      (operation, document) =>
+        this.getAuthorization().requirements(document, operation.name)
+    );
+  }
+
+  // Built on first use, with the names the cost directives have in the
+  // supergraph.
+  private getDemandControl(): DemandControl {
+    if (this.demandControl == null) {
+      this.demandControl = new DemandControl(
+        this.getGraphqlSupergraph(),
+        this.directiveName(COST_SPEC_IDENTITY, "cost"),
+        this.directiveName(COST_SPEC_IDENTITY, "listSize")
+      );
+    }
+    return this.demandControl;
+  }
+
+  // Built on first use, with the names the authorization directives have in
+  // the supergraph.
+  private getAuthorization(): Authorization {
+    if (this.authorization == null) {
+      this.authorization = new Authorization(this.getGraphqlSupergraph(), {
+        authenticated: this.directiveName(
+          AUTHENTICATED_SPEC_IDENTITY,
+          "authenticated"
+        ),
+        requiresScopes: this.directiveName(
+          REQUIRES_SCOPES_SPEC_IDENTITY,
+          "requiresScopes"
+        ),
+        policy: this.directiveName(POLICY_SPEC_IDENTITY, "policy"),
+      });
+    }
+    return this.authorization;
+  }
+
+  // The supergraph as a graphql-js schema, where the arguments of the
+  // directives applied to schema elements are kept on their AST nodes.
+  private getGraphqlSupergraph(): GraphQLSchema {
+    if (this.graphqlSupergraph == null) {
+      this.graphqlSupergraph = buildSchema(this.schemaString, {
+        assumeValidSDL: true,
+      });
+    }
+    return this.graphqlSupergraph;
+  }
+
+  // The name of a directive of a feature linked by the supergraph, which
+  // depends on how the feature was imported.
+  private directiveName(
+    identity: string,
+    directive: string
+  ): string | undefined {
+    return this.supergraph.schema.coreFeatures
+      ?.getByIdentity(identity)
+      ?.directiveNameInSchema(directive);
+  }
+
   getApiSchema(): string {
     return printSchema(this.apiSchema);
   }
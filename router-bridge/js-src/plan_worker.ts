@@ -4,9 +4,13 @@ import {
   BridgeQueryPlanner,
   ExecutionResultWithUsageReporting,
   PlanOptions,
+  NormalizedOperation,
   QueryPlanResult,
+  ValidatedOperation,
 } from "./plan";
-import { QueryPlannerConfigExt } from "./types";
+import { OperationResult, QueryPlannerConfigExt } from "./types";
+import { DemandCost, DemandCostOptions } from "./demand_control";
+import { AuthorizationRequirements } from "./authorization";
 declare let bridge: { BridgeQueryPlanner: typeof BridgeQueryPlanner };
 declare namespace Deno {
   namespace core {
@@ -15,7 +19,14 @@ declare namespace Deno {
   }
 }
 
-let logFunction: (message: string) => void;
+// Structured fields attached to logs, as tracing fields.
+// The schema id is attached by the bridge, as it doesn't fit in a number.
+interface LogFields {
+  operationName?: string;
+  // The kind of event being processed
+  phase?: string;
+}
+let logFunction: (message: string, fields?: LogFields) => void;
 declare let logger: {
   trace: typeof logFunction;
   debug: typeof logFunction;
@@ -23,6 +34,8 @@ declare let logger: {
   warn: typeof logFunction;
   error: typeof logFunction;
 };
+// Attached to every log, see runtime.js
+declare let logContext: LogFields;
 
 enum PlannerEventKind {
   UpdateSchema = "UpdateSchema",
@@ -32,6 +45,13 @@ enum PlannerEventKind {
   Introspect = "Introspect",
   Signature = "Signature",
   Subgraphs = "Subgraphs",
+  PlanBatch = "PlanBatch",
+  DemandCost = "DemandCost",
+  AuthorizationRequirements = "AuthorizationRequirements",
+  Validate = "Validate",
+  CoerceVariables = "CoerceVariables",
+  NormalizedOperation = "NormalizedOperation",
+  ValidateBatch = "ValidateBatch",
 }
 
 interface UpdateSchemaEvent {
@@ -48,6 +68,63 @@ interface PlanEvent {
   schemaId: number;
   options?: PlanOptions;
 }
+
+interface BatchOperation {
+  query: string;
+  operationName?: string;
+  options?: PlanOptions;
+}
+
+interface PlanBatchEvent {
+  kind: PlannerEventKind.PlanBatch;
+  operations: BatchOperation[];
+  schemaId: number;
+}
+
+interface NormalizedOperationEvent {
+  kind: PlannerEventKind.NormalizedOperation;
+  query: string;
+  operationName?: string;
+  schemaId: number;
+}
+
+interface CoerceVariablesEvent {
+  kind: PlannerEventKind.CoerceVariables;
+  query: string;
+  operationName?: string;
+  variables: Record<string, unknown>;
+  schemaId: number;
+}
+
+interface DemandCostEvent {
+  kind: PlannerEventKind.DemandCost;
+  query: string;
+  operationName?: string;
+  variables: Record<string, unknown>;
+  options: DemandCostOptions;
+  schemaId: number;
+}
+
+interface ValidateEvent {
+  kind: PlannerEventKind.Validate;
+  query: string;
+  operationName?: string;
+  schemaId: number;
+}
+
+interface ValidateBatchEvent {
+  kind: PlannerEventKind.ValidateBatch;
+  queries: string[];
+  schemaId: number;
+}
+
+interface AuthorizationRequirementsEvent {
+  kind: PlannerEventKind.AuthorizationRequirements;
+  query: string;
+  operationName?: string;
+  schemaId: number;
+}
+
 interface ApiSchemaEvent {
   kind: PlannerEventKind.ApiSchema;
   schemaId: number;
@@ -78,6 +155,13 @@ interface Exit {
 type PlannerEvent =
   | UpdateSchemaEvent
   | PlanEvent
+  | PlanBatchEvent
+  | ValidateEvent
+  | ValidateBatchEvent
+  | CoerceVariablesEvent
+  | NormalizedOperationEvent
+  | DemandCostEvent
+  | AuthorizationRequirementsEvent
   | ApiSchemaEvent
   | IntrospectEvent
   | SignatureEvent
@@ -94,6 +178,13 @@ type WorkerResultWithId = {
 };
 type WorkerResult =
   | PlanResult
+  | PlanResult[]
+  | ExecutionResultWithUsageReporting<ValidatedOperation>
+  | (ExecutionResultWithUsageReporting<ValidatedOperation> | PlanResult)[]
+  | ExecutionResultWithUsageReporting<OperationResult>
+  | ExecutionResultWithUsageReporting<NormalizedOperation>
+  | ExecutionResultWithUsageReporting<DemandCost>
+  | ExecutionResultWithUsageReporting<AuthorizationRequirements>
   | ApiSchemaResult
   | ExecutionResult
   | Map<string, string>
@@ -198,6 +289,57 @@ const intoSerializableGraphQLErrorExt = (
   };
 };
 
+// The payload sent back when an unexpected error happened while planning.
+const intoPlanningFailure = (e: any): PlanResult => {
+  const unexpectedError = {
+    name: e.name || "unknown",
+    message: e.message || "",
+    extensions: {
+      code: "QUERY_PLANNING_FAILED",
+      exception: {
+        stacktrace: e.toString(),
+      },
+    },
+  };
+
+  return {
+    errors: [unexpectedError],
+    usageReporting: {
+      statsReportKey: "",
+      referencedFieldsByType: {},
+    },
+  } as PlanResult;
+};
+
+// Plan every operation of a batch, so that an operation failing doesn't fail the others.
+const planBatch = (
+  planner: BridgeQueryPlanner,
+  operations: BatchOperation[]
+): PlanResult[] =>
+  operations.map(({ query, operationName, options }) => {
+    logContext = { ...logContext, operationName };
+    try {
+      return planner.plan(query, operationName, options);
+    } catch (e) {
+      logger.warn(`an error happened while planning a batch operation ${e}`);
+      return intoPlanningFailure(e);
+    }
+  });
+
+// Validate every query of a batch, so that a query failing doesn't fail the others.
+const validateBatch = (
+  planner: BridgeQueryPlanner,
+  queries: string[]
+): (ExecutionResultWithUsageReporting<ValidatedOperation> | PlanResult)[] =>
+  queries.map((query) => {
+    try {
+      return planner.validate(query);
+    } catch (e) {
+      logger.warn(`an error happened while validating a batch query ${e}`);
+      return intoPlanningFailure(e);
+    }
+  });
+
 const send = async (payload: WorkerResultWithId): Promise<void> => {
   logger.trace(`plan_worker: sending payload ${JSON.stringify(payload)}`);
   await Deno.core.ops.send(payload);
@@ -249,9 +391,26 @@ async function run() {
     // there won't be any messageId to send a payload back to the router,
     // unless we keep it in this scope.
     let messageId = "";
+    logContext = {};
+    let received: PlannerEventWithId;
+    try {
+      received = await receive();
+    } catch (e) {
+      // Receiving only fails once the worker is shut down,
+      // and every request sent to it was received.
+      logger.debug(`plan_worker: exiting, ${e}`);
+      return;
+    }
     try {
-      const { id, payload: event } = await receive();
+      const { id, payload: event } = received;
       messageId = id;
+      logContext = {
+        operationName:
+          event != null && "operationName" in event
+            ? event.operationName
+            : undefined,
+        phase: event?.kind,
+      };
       try {
         switch (event?.kind) {
           case PlannerEventKind.UpdateSchema:
@@ -268,6 +427,59 @@ async function run() {
               .plan(event.query, event.operationName, event.options);
             await send({ id, payload: planResult });
             break;
+          case PlannerEventKind.PlanBatch:
+            const batchResults = planBatch(
+              planners.get(event.schemaId),
+              event.operations
+            );
+            await send({ id, payload: batchResults });
+            break;
+          case PlannerEventKind.Validate:
+            const validation = planners
+              .get(event.schemaId)
+              .validate(event.query, event.operationName);
+            await send({ id, payload: validation });
+            break;
+          case PlannerEventKind.NormalizedOperation:
+            const normalized = planners
+              .get(event.schemaId)
+              .normalizedOperation(event.query, event.operationName);
+            await send({ id, payload: normalized });
+            break;
+          case PlannerEventKind.CoerceVariables:
+            const coercion = planners
+              .get(event.schemaId)
+              .coerceVariables(
+                event.query,
+                event.operationName,
+                event.variables
+              );
+            await send({ id, payload: coercion });
+            break;
+          case PlannerEventKind.ValidateBatch:
+            const validations = validateBatch(
+              planners.get(event.schemaId),
+              event.queries
+            );
+            await send({ id, payload: validations });
+            break;
+          case PlannerEventKind.DemandCost:
+            const demandCost = planners
+              .get(event.schemaId)
+              .demandCost(
+                event.query,
+                event.operationName,
+                event.variables,
+                event.options
+              );
+            await send({ id, payload: demandCost });
+            break;
+          case PlannerEventKind.AuthorizationRequirements:
+            const requirements = planners
+              .get(event.schemaId)
+              .authorizationRequirements(event.query, event.operationName);
+            await send({ id, payload: requirements });
+            break;
           case PlannerEventKind.ApiSchema:
             const apiSchemaResult = planners.get(event.schemaId).getApiSchema();
             const payload: ApiSchemaResult = { schema: apiSchemaResult };
@@ -298,58 +510,18 @@ async function run() {
               break;
             }
           default:
-            logger.warn(`unknown message received: ${JSON.stringify(event)}\n`);
+            logger.warn(`unknown message received: ${JSON.stringify(event)}`);
             break;
         }
       } catch (e) {
-        logger.warn(`an error happened in the worker runtime ${e}\n`);
-
-        const unexpectedError = {
-          name: e.name || "unknown",
-          message: e.message || "",
-          extensions: {
-            code: "QUERY_PLANNING_FAILED",
-            exception: {
-              stacktrace: e.toString(),
-            },
-          },
-        };
-
-        await send({
-          id,
-          payload: {
-            errors: [unexpectedError],
-            usageReporting: {
-              statsReportKey: "",
-              referencedFieldsByType: {},
-            },
-          },
-        });
+        logger.warn(`an error happened in the worker runtime ${e}`);
+
+        await send({ id, payload: intoPlanningFailure(e) });
       }
     } catch (e) {
-      logger.warn(`plan_worker: an unknown error occurred ${e}\n`);
-
-      const unexpectedError = {
-        name: e.name || "unknown",
-        message: e.message || "",
-        extensions: {
-          code: "QUERY_PLANNING_FAILED",
-          exception: {
-            stacktrace: e.toString(),
-          },
-        },
-      };
+      logger.warn(`plan_worker: an unknown error occurred ${e}`);
 
-      await send({
-        id: messageId,
-        payload: {
-          errors: [unexpectedError],
-          usageReporting: {
-            statsReportKey: "",
-            referencedFieldsByType: {},
-          },
-        },
-      });
+      await send({ id: messageId, payload: intoPlanningFailure(e) });
     }
   }
 }
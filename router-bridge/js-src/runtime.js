@@ -1,10 +1,21 @@
-// We define logging capabilities, which can be gathered by tracing
+// We define logging capabilities, which can be gathered by tracing.
+// The fields of `logContext` describe what the worker is doing, and are
+// attached to every log as structured fields, along with the `fields` of the
+// log itself.
+logContext = {};
+const logWithContext = (op, message, fields) =>
+  op(message.toString(), { ...logContext, ...fields });
 logger = {
-  trace: (message) => Deno.core.ops.log_trace(`${message.toString()}\n`),
-  debug: (message) => Deno.core.ops.log_debug(`${message.toString()}\n`),
-  info: (message) => Deno.core.ops.log_info(`${message.toString()}\n`),
-  warn: (message) => Deno.core.ops.log_warn(`${message.toString()}\n`),
-  error: (message) => Deno.core.ops.log_error(`${message.toString()}\n`),
+  trace: (message, fields) =>
+    logWithContext(Deno.core.ops.log_trace, message, fields),
+  debug: (message, fields) =>
+    logWithContext(Deno.core.ops.log_debug, message, fields),
+  info: (message, fields) =>
+    logWithContext(Deno.core.ops.log_info, message, fields),
+  warn: (message, fields) =>
+    logWithContext(Deno.core.ops.log_warn, message, fields),
+  error: (message, fields) =>
+    logWithContext(Deno.core.ops.log_error, message, fields),
 };
 
 // We define a print function that uses
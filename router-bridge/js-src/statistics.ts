@@ -0,0 +1,42 @@
+declare namespace Deno {
+  namespace core {
+    const ops: Record<string, (...args: unknown[]) => any>;
+  }
+}
+
+// How long each phase of planning took, in milliseconds. A phase is missing if
+// planning stopped before it.
+export interface PlanningPhases {
+  // Includes the token limit check
+  parsing?: number;
+  // Includes the operation limits check
+  validation?: number;
+  operationFromDocument?: number;
+  usageReporting?: number;
+  queryPlanning?: number;
+  formatting?: number;
+}
+
+export interface PlanningStatistics {
+  phases: PlanningPhases;
+  // The number of plans the query planner evaluated, which is bounded by
+  // `debug.maxEvaluatedPlans`
+  evaluatedPlanCount?: number;
+}
+
+// Measures consecutive phases of planning.
+//
+// `performance.now` isn't available in the worker, and `Date.now` only has a
+// millisecond resolution, so the phases are timed with a monotonic clock of the
+// bridge, which has a microsecond resolution.
+export class PhaseTimer {
+  readonly phases: PlanningPhases = {};
+  private phaseStart: number = Deno.core.ops.monotonic_now();
+
+  // Records the time since the previous phase ended as the duration of `phase`.
+  end(phase: keyof PlanningPhases) {
+    const now = Deno.core.ops.monotonic_now();
+    this.phases[phase] = now - this.phaseStart;
+    this.phaseStart = now;
+  }
+}
@@ -5,7 +5,11 @@ declare namespace Deno {
   }
 }
 
-let logFunction: (message: string) => void;
+interface LogFields {
+  operationName?: string;
+  phase?: string;
+}
+let logFunction: (message: string, fields?: LogFields) => void;
 declare let logger: {
   trace: typeof logFunction;
   debug: typeof logFunction;
@@ -13,6 +17,7 @@ declare let logger: {
   warn: typeof logFunction;
   error: typeof logFunction;
 };
+declare let logContext: LogFields;
 
 enum CommandKind {
   Trace = "Trace",
@@ -20,7 +25,10 @@ enum CommandKind {
   Info = "Info",
   Warn = "Warn",
   Error = "Error",
+  Structured = "Structured",
   Exit = "Exit",
+  Crash = "Crash",
+  Allocate = "Allocate",
 }
 
 type Payload = {
@@ -46,8 +54,14 @@ const receive = async (): Promise<Command> =>
 
 async function run() {
   while (true) {
+    let event: Command;
+    try {
+      event = await receive();
+    } catch (e) {
+      // The worker was shut down
+      return;
+    }
     try {
-      const event = await receive();
       const {
         id,
         payload: { kind, message },
@@ -73,9 +87,25 @@ async function run() {
           logger.error(message);
           await send({ id, payload: true });
           break;
+        case CommandKind.Structured:
+          logContext = { phase: "Plan" };
+          logger.info(message, { operationName: "Me" });
+          logContext = {};
+          await send({ id, payload: true });
+          break;
         case CommandKind.Exit:
           await send({ id, payload: true });
           return;
+        case CommandKind.Crash:
+          // An unhandled rejection makes the event loop fail.
+          Promise.reject(new Error(message));
+          break;
+        case CommandKind.Allocate:
+          // Allocate until the heap runs out of memory.
+          const chunks = [];
+          while (true) {
+            chunks.push(new Array(1_000_000).fill(chunks.length));
+          }
         default:
           logger.error(`unknown message received: ${JSON.stringify(event)}\n`);
           break;
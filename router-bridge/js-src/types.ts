@@ -4,9 +4,18 @@ export type OperationResult =
   | { Ok: any; Err?: undefined }
   | { Ok?: undefined; Err: any };
 
+export interface OperationLimits {
+  maxDepth?: number | null;
+  maxAliases?: number | null;
+  maxRootFields?: number | null;
+  maxTokens?: number | null;
+}
+
 export interface QueryPlannerConfigExt extends QueryPlannerConfig {
   graphqlValidation?: boolean;
   typeConditionedFetching?: boolean;
+  operationLimits?: OperationLimits;
+  planningStatistics?: boolean;
 }
 
 // `lru-cache` (in our dependencies) uses the global `AbortSignal` type
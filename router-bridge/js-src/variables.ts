@@ -0,0 +1,93 @@
+import {
+  coerceInputValue,
+  DocumentNode,
+  getOperationAST,
+  GraphQLSchema,
+  isInputType,
+  isNonNullType,
+  print,
+  typeFromAST,
+  valueFromAST,
+} from "graphql";
+import { OperationResult } from "./types";
+
+const VARIABLE_VALIDATION_FAILURE_EXT_CODE: string =
+  "VALIDATION_INVALID_TYPE_VARIABLE";
+
+export interface VariableError {
+  message: string;
+  // The JSON path of the invalid value in the variables object.
+  path: (string | number)[];
+  extensions: {
+    code: string;
+  };
+}
+
+// Coerces `inputs` against the variable definitions of the operation,
+// following the GraphQL input coercion rules. Every invalid value is
+// reported, along with its JSON path.
+//
+// `document` must have been validated against `schema`.
+export function coerceVariables(
+  schema: GraphQLSchema,
+  document: DocumentNode,
+  operationName: string | undefined,
+  inputs: Record<string, unknown>
+): OperationResult {
+  const operation = getOperationAST(document, operationName);
+  const coerced: Record<string, unknown> = {};
+  const errors: VariableError[] = [];
+  const error = (message: string, path: (string | number)[]) =>
+    errors.push({
+      message,
+      path,
+      extensions: { code: VARIABLE_VALIDATION_FAILURE_EXT_CODE },
+    });
+
+  for (const definition of operation?.variableDefinitions ?? []) {
+    const name = definition.variable.name.value;
+    const type = typeFromAST(schema, definition.type);
+    if (!isInputType(type)) {
+      error(
+        `Variable "$${name}" expected value of type "${print(
+          definition.type
+        )}" which cannot be used as an input type.`,
+        [name]
+      );
+      continue;
+    }
+
+    if (!Object.prototype.hasOwnProperty.call(inputs, name)) {
+      if (definition.defaultValue != null) {
+        coerced[name] = valueFromAST(definition.defaultValue, type);
+      } else if (isNonNullType(type)) {
+        error(
+          `Variable "$${name}" of required type "${type}" was not provided.`,
+          [name]
+        );
+      }
+      continue;
+    }
+
+    const value = inputs[name];
+    if (value === null && isNonNullType(type)) {
+      error(
+        `Variable "$${name}" of non-null type "${type}" must not be null.`,
+        [name]
+      );
+      continue;
+    }
+
+    coerced[name] = coerceInputValue(value, type, (path, invalidValue, e) => {
+      const at = path.length > 0 ? ` at "${[name, ...path].join(".")}"` : "";
+      error(
+        `Variable "$${name}" got invalid value ${JSON.stringify(
+          invalidValue
+        )}${at}; ${e.message}`,
+        [name, ...path]
+      );
+    });
+  }
+
+  return errors.length > 0 ? { Err: errors } : { Ok: coerced };
+}
@@ -0,0 +1,40 @@
+/*!
+# The authorization requirements of operations.
+
+The `@authenticated`, `@requiresScopes` and `@policy` directives of the supergraph that an
+operation touches are collected without planning it.
+See [`Planner::authorization_requirements`](crate::planner::Planner::authorization_requirements).
+*/
+
+use serde::{Deserialize, Serialize};
+
+/// The authorization requirements of an operation.
+///
+/// Every selection is taken into account, whatever its `@skip` or `@include` directives.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizationRequirements {
+    /// The schema coordinates with an `@authenticated` directive
+    pub authenticated: Vec<String>,
+    /// The `@requiresScopes` directives, and where they apply
+    pub scopes: Vec<Requirement>,
+    /// The `@policy` directives, and where they apply
+    pub policies: Vec<Requirement>,
+}
+
+impl AuthorizationRequirements {
+    /// Return true if the operation has no authorization requirement
+    pub fn is_empty(&self) -> bool {
+        self.authenticated.is_empty() && self.scopes.is_empty() && self.policies.is_empty()
+    }
+}
+
+/// A `@requiresScopes` or `@policy` directive applied to a type or a field selected by an operation.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Requirement {
+    /// The schema coordinate of the type or field, like `User` or `User.email`
+    pub coordinate: String,
+    /// The requirement is met if every scope or policy of one of these sets is
+    pub any_of: Vec<Vec<String>>,
+}
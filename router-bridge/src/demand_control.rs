@@ -0,0 +1,41 @@
+/*!
+# Demand control: the estimated cost of operations.
+
+The cost is computed from the `@cost` and `@listSize` directives of the supergraph,
+and from the variables of the operation, before it is planned or executed.
+See [`Planner::demand_cost`](crate::planner::Planner::demand_cost).
+*/
+
+use serde::{Deserialize, Serialize};
+
+/// Options for estimating the cost of an operation.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct DemandCostOptions {
+    /// The size assumed for lists that have no `@listSize`, or whose slicing arguments are not set
+    pub default_list_size: u32,
+}
+
+/// The estimated cost of an operation.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DemandCost {
+    /// The cost of the whole operation
+    pub cost: f64,
+    /// The cost of each field of the operation, in document order.
+    ///
+    /// Fields that are selected from fragments are listed under the path of the fragment spread.
+    pub fields: Vec<FieldCost>,
+}
+
+/// The estimated cost of a field of an operation.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldCost {
+    /// The response path of the field, without list indexes
+    pub path: Vec<String>,
+    /// The schema coordinate of the field, like `Query.products`
+    pub coordinate: String,
+    /// The cost of the field, including its arguments and its selections, times the size of its list
+    pub cost: f64,
+}
@@ -3,6 +3,7 @@
 */
 
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Serialize, Deserialize, Debug, Clone)]
@@ -36,4 +37,44 @@ pub enum Error {
         /// The deno response id we tried to deserialize.
         id: String,
     },
+
+    /// The request didn't complete within the allotted time.
+    ///
+    /// The javascript worker was interrupted, and remains usable for other requests.
+    #[error("the request timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// The javascript heap ran out of memory while processing the request,
+    /// and couldn't grow any further.
+    ///
+    /// The request was interrupted, and the javascript worker remains usable for other requests.
+    #[error("the javascript heap reached its limit of {limit_mb}MB")]
+    HeapLimitExceeded {
+        /// The ceiling of the heap, in megabytes
+        limit_mb: usize,
+    },
+
+    /// The [`HeapPolicy`](crate::heap::HeapPolicy) of the javascript workers can't grow the heap.
+    ///
+    /// This contains the validation error message.
+    #[error("{0}")]
+    InvalidHeapPolicy(String),
+
+    /// The request queue of the javascript worker was full,
+    /// and its [`QueuePolicy`](crate::queue::QueuePolicy) rejects requests when it is.
+    ///
+    /// The request wasn't sent, and can be retried once the worker catches up.
+    #[error("the request queue of the javascript worker is full ({capacity} requests)")]
+    QueueFull {
+        /// The capacity of the queue
+        capacity: usize,
+    },
+
+    /// The persisted query manifest of the planner doesn't have an operation with this id,
+    /// or the planner has no manifest.
+    #[error("the persisted query `{id}` is not in the manifest")]
+    UnknownPersistedQuery {
+        /// The id of the persisted query
+        id: String,
+    },
 }
@@ -0,0 +1,43 @@
+/*!
+# Memory usage of the JavaScript runtimes backing the bridge.
+*/
+
+pub(crate) use apollo_federation_types::heap::NextHeapLimit;
+// The heap policy and statistics are shared with composition
+pub use apollo_federation_types::heap::{
+    HeapLimitAction, HeapPolicy, HeapStatistics, InvalidHeapPolicy,
+};
+
+/// Take a snapshot of the heap of `isolate`.
+pub(crate) fn heap_statistics(
+    isolate: &mut deno_core::v8::Isolate,
+    heap_expansions: usize,
+) -> HeapStatistics {
+    let mut statistics = deno_core::v8::HeapStatistics::default();
+    isolate.get_heap_statistics(&mut statistics);
+
+    HeapStatistics {
+        used_heap_size: statistics.used_heap_size(),
+        total_heap_size: statistics.total_heap_size(),
+        heap_size_limit: statistics.heap_size_limit(),
+        external_memory: statistics.external_memory(),
+        heap_expansions,
+    }
+}
+
+// A reasonable default starting limit for our deno heap.
+const APOLLO_ROUTER_BRIDGE_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE_DEFAULT: usize = 256;
+
+/// The heap policy of the JavaScript workers, unless another one is provided.
+///
+/// The initial limit can be set with the `APOLLO_ROUTER_BRIDGE_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE`
+/// environment variable, and defaults to 256MB. The heap grows by 1.25x, without a ceiling.
+pub fn default_heap_policy() -> HeapPolicy {
+    HeapPolicy {
+        initial_limit_mb: std::env::var("APOLLO_ROUTER_BRIDGE_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(APOLLO_ROUTER_BRIDGE_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE_DEFAULT),
+        ..Default::default()
+    }
+}
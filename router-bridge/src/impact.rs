@@ -0,0 +1,288 @@
+/*!
+# Compare the query plans of a set of operations between two versions of a schema.
+
+This tells which known operations would be planned differently, or would stop validating,
+before a new supergraph is rolled out.
+*/
+
+use crate::plan_types::{FetchNode, PlanNode, QueryPlanResult};
+use crate::planner::{
+    PlanErrors, PlanOptions, PlanResult, Planner, PlannerError, QueryPlannerConfig,
+};
+use serde::Serialize;
+use thiserror::Error;
+
+/// An operation to plan against both schemas.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Operation {
+    /// The operation document
+    pub query: String,
+    /// The name of the operation to plan, if the document holds more than one
+    pub operation_name: Option<String>,
+    /// The planning options
+    pub options: PlanOptions,
+}
+
+/// An error that prevented the impact analysis from running.
+#[derive(Debug, Error)]
+pub enum ImpactError {
+    /// The candidate schema couldn't be loaded in the query planner.
+    #[error("the candidate schema is invalid: {0:?}")]
+    CandidateSchema(Vec<PlannerError>),
+    /// The operations couldn't be sent to the query planner.
+    #[error(transparent)]
+    Bridge(#[from] crate::error::Error),
+}
+
+/// The impact of a schema change on an operation.
+#[derive(Debug, Clone)]
+pub struct OperationImpact {
+    /// The operation that was planned
+    pub operation: Operation,
+    /// How its plan changed
+    pub change: PlanChange,
+}
+
+/// How the plan of an operation changed between the current and the candidate schema.
+#[derive(Debug, Clone)]
+pub enum PlanChange {
+    /// The `formattedQueryPlan` is the same with both schemas
+    Unchanged,
+    /// The `formattedQueryPlan` changed
+    Changed(FetchChanges),
+    /// The operation is planned against the current schema, but doesn't validate against the candidate
+    NoLongerValid(PlanErrors),
+    /// The operation doesn't validate against the current schema, so there is nothing to compare
+    Invalid(PlanErrors),
+}
+
+impl PlanChange {
+    /// Return true if the `formattedQueryPlan` of the operation changed
+    pub fn formatted_query_plan_changed(&self) -> bool {
+        matches!(self, PlanChange::Changed(_))
+    }
+}
+
+/// The fetches that appear in only one of the two plans of an operation.
+///
+/// Fetches are matched by their location in the response and their content,
+/// so a fetch that changed shows up both as removed and added.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FetchChanges {
+    /// The fetches of the current plan that are not in the candidate plan
+    pub removed: Vec<PlannedFetch>,
+    /// The fetches of the candidate plan that are not in the current plan
+    pub added: Vec<PlannedFetch>,
+}
+
+/// A fetch node, and where in the response its result is merged.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedFetch {
+    /// The response path of the enclosing `Flatten` node, empty for root fetches
+    pub path: Vec<String>,
+    /// The fetch
+    pub fetch: FetchNode,
+}
+
+/// Plan `operations` against the schema of `planner` and against `candidate_schema`,
+/// and report how each of their plans would change.
+///
+/// The candidate schema is loaded with [`Planner::update`], so `planner` is left untouched.
+/// The impacts are in the same order as `operations`.
+pub async fn plan_impact(
+    planner: &Planner<QueryPlanResult>,
+    candidate_schema: String,
+    config: QueryPlannerConfig,
+    operations: Vec<Operation>,
+) -> Result<Vec<OperationImpact>, ImpactError> {
+    let candidate = planner
+        .update(candidate_schema, config)
+        .await
+        .map_err(ImpactError::CandidateSchema)?;
+
+    let batch: Vec<_> = operations
+        .iter()
+        .map(|operation| {
+            (
+                operation.query.clone(),
+                operation.operation_name.clone(),
+                operation.options.clone(),
+            )
+        })
+        .collect();
+    let current_plans = planner.plan_batch(batch.clone()).await?;
+    let candidate_plans = candidate.plan_batch(batch).await?;
+
+    Ok(operations
+        .into_iter()
+        .zip(current_plans.into_iter().zip(candidate_plans))
+        .map(|(operation, (current, candidate))| OperationImpact {
+            operation,
+            change: compare(current, candidate),
+        })
+        .collect())
+}
+
+fn compare(
+    current: PlanResult<QueryPlanResult>,
+    candidate: PlanResult<QueryPlanResult>,
+) -> PlanChange {
+    let current = match current.into_result() {
+        Ok(current) => current.data,
+        Err(errors) => return PlanChange::Invalid(errors),
+    };
+    let candidate = match candidate.into_result() {
+        Ok(candidate) => candidate.data,
+        Err(errors) => return PlanChange::NoLongerValid(errors),
+    };
+
+    if current.formatted_query_plan == candidate.formatted_query_plan {
+        return PlanChange::Unchanged;
+    }
+
+    let mut removed = planned_fetches(current.query_plan.node.as_ref());
+    let mut added = Vec::new();
+    for fetch in planned_fetches(candidate.query_plan.node.as_ref()) {
+        match removed.iter().position(|current| current == &fetch) {
+            Some(index) => {
+                removed.remove(index);
+            }
+            None => added.push(fetch),
+        }
+    }
+
+    PlanChange::Changed(FetchChanges { removed, added })
+}
+
+/// Collect the fetches of a plan, in the order they appear in it.
+fn planned_fetches(node: Option<&PlanNode>) -> Vec<PlannedFetch> {
+    fn visit(node: &PlanNode, path: &[String], fetches: &mut Vec<PlannedFetch>) {
+        match node {
+            PlanNode::Sequence { nodes } | PlanNode::Parallel { nodes } => {
+                for node in nodes {
+                    visit(node, path, fetches);
+                }
+            }
+            PlanNode::Fetch(fetch) => fetches.push(PlannedFetch {
+                path: path.to_vec(),
+                fetch: fetch.clone(),
+            }),
+            PlanNode::Flatten(flatten) => visit(&flatten.node, &flatten.path, fetches),
+            PlanNode::Defer { primary, deferred } => {
+                if let Some(node) = &primary.node {
+                    visit(node, path, fetches);
+                }
+                for deferred in deferred {
+                    if let Some(node) = &deferred.node {
+                        visit(node, path, fetches);
+                    }
+                }
+            }
+            PlanNode::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => {
+                for clause in if_clause.iter().chain(else_clause.iter()) {
+                    visit(clause, path, fetches);
+                }
+            }
+            PlanNode::Subscription { primary, rest } => {
+                fetches.push(PlannedFetch {
+                    path: path.to_vec(),
+                    fetch: primary.clone(),
+                });
+                if let Some(rest) = rest {
+                    visit(rest, path, fetches);
+                }
+            }
+        }
+    }
+
+    let mut fetches = Vec::new();
+    if let Some(node) = node {
+        visit(node, &[], &mut fetches);
+    }
+    fetches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = include_str!("testdata/schema.graphql");
+    const SCHEMA_WITHOUT_REVIEW_BODY: &str =
+        include_str!("testdata/schema_without_review_body.graphql");
+
+    fn operation(query: &str) -> Operation {
+        Operation {
+            query: query.to_string(),
+            operation_name: None,
+            options: PlanOptions::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn impact_of_a_schema_change() {
+        let planner =
+            Planner::<QueryPlanResult>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
+
+        let impacts = plan_impact(
+            &planner,
+            SCHEMA_WITHOUT_REVIEW_BODY.to_string(),
+            QueryPlannerConfig::default(),
+            vec![
+                operation("{ me { id } }"),
+                operation("{ me { reviews { body } } }"),
+                operation("{ unknownField }"),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(impacts[0].change, PlanChange::Unchanged));
+        assert!(matches!(impacts[1].change, PlanChange::NoLongerValid(_)));
+        assert!(matches!(impacts[2].change, PlanChange::Invalid(_)));
+    }
+
+    #[tokio::test]
+    async fn fetch_changes() {
+        let planner =
+            Planner::<QueryPlanResult>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
+        let query = "{ me { id name { first } } }";
+        let plan = planner
+            .plan(query.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap();
+        let current_fetches = planned_fetches(
+            plan.data
+                .as_ref()
+                .and_then(|data| data.query_plan.node.as_ref()),
+        );
+        assert_eq!(1, current_fetches.len());
+        assert!(current_fetches[0].path.is_empty());
+
+        // the same plan with a different subgraph operation
+        let mut candidate = planner
+            .plan(query.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap();
+        if let Some(data) = candidate.data.as_mut() {
+            data.formatted_query_plan = Some("changed".to_string());
+            if let Some(PlanNode::Fetch(fetch)) = data.query_plan.node.as_mut() {
+                fetch.operation = "{me{id}}".to_string();
+            }
+        }
+
+        let PlanChange::Changed(changes) = compare(plan, candidate) else {
+            panic!("the plan should have changed");
+        };
+        assert_eq!(current_fetches, changes.removed);
+        assert_eq!(1, changes.added.len());
+        assert_eq!("{me{id}}", changes.added[0].fetch.operation);
+    }
+}
@@ -312,6 +312,8 @@ fragment TypeRef on __Type {
                 generate_query_fragments: None,
                 debug: Default::default(),
                 type_conditioned_fetching: false,
+                operation_limits: Default::default(),
+                planning_statistics: false,
             },
         )
         .unwrap();
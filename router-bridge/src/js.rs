@@ -1,17 +1,20 @@
 use crate::error::Error;
+use crate::heap::{HeapLimitAction, HeapPolicy, NextHeapLimit};
 #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
 use deno_core::Snapshot;
 /// Wraps creating the Deno Js runtime collecting parameters and executing a script.
 use deno_core::{Extension, JsRuntime, RuntimeOptions};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-
-// A reasonable default starting limit for our deno heap.
-const APOLLO_ROUTER_BRIDGE_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE_DEFAULT: &str = "256";
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 pub(crate) struct Js {
     name: String,
     parameters: Vec<(&'static str, String)>,
+    heap_policy: HeapPolicy,
+    heap_expansions: Arc<AtomicUsize>,
+    heap_limit_reached: Arc<AtomicBool>,
 }
 
 impl Js {
@@ -19,9 +22,24 @@ impl Js {
         Js {
             name,
             parameters: Vec::new(),
+            heap_policy: crate::heap::default_heap_policy(),
+            heap_expansions: Default::default(),
+            heap_limit_reached: Default::default(),
         }
     }
 
+    /// Grow the heap of the runtimes we build according to `heap_policy`.
+    pub(crate) fn with_heap_policy(mut self, heap_policy: HeapPolicy) -> Js {
+        self.heap_policy = heap_policy;
+        self
+    }
+
+    /// Count the heap expansions of the runtimes we build in `heap_expansions`.
+    pub(crate) fn with_heap_expansions(mut self, heap_expansions: Arc<AtomicUsize>) -> Js {
+        self.heap_expansions = heap_expansions;
+        self
+    }
+
     pub(crate) fn with_parameter<T: Serialize>(
         mut self,
         name: &'static str,
@@ -71,6 +89,7 @@ impl Js {
                     ))),
                 }
             }
+            Err(_) if self.take_heap_limit_reached() => Err(self.heap_limit_exceeded()),
             Err(e) => {
                 let message =
                     format!("unable to invoke `{name}` in JavaScript runtime \n error: \n {e:?}");
@@ -83,30 +102,10 @@ impl Js {
         // Initialize a runtime instance
         let buffer = include_bytes!(concat!(env!("OUT_DIR"), "/query_runtime.snap"));
 
-        let heap_size =
-            match std::env::var("APOLLO_ROUTER_BRIDGE_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE") {
-                Ok(v) => v,
-                Err(_e) => {
-                    APOLLO_ROUTER_BRIDGE_EXPERIMENTAL_V8_INITIAL_HEAP_SIZE_DEFAULT.to_string()
-                }
-            };
-
-        // The first flag is argv[0], so provide an ignorable value
-        let flags = vec![
-            "--ignored".to_string(),
-            "--max-heap-size".to_string(),
-            heap_size,
-        ];
-
-        // Deno will warn us if we supply flags it doesn't recognise.
-        // We ignore "--ignored" and report any others as warnings
-        let ignored: Vec<_> = deno_core::v8_set_flags(flags)
-            .into_iter()
-            .filter(|x| x != "--ignored")
-            .collect();
-        if !ignored.is_empty() {
-            tracing::warn!("deno ignored these flags: {:?}", ignored);
-        }
+        // The heap limits are set on the isolate rather than with V8 flags,
+        // which are global to the process.
+        let create_params =
+            deno_core::v8::CreateParams::default().heap_limits(0, self.heap_policy.initial_limit());
 
         #[derive(Clone)]
         struct Permissions;
@@ -132,6 +131,7 @@ impl Js {
                 my_ext,
             ],
             startup_snapshot: Some(Snapshot::Static(buffer)),
+            create_params: Some(create_params),
             ..Default::default()
         });
 
@@ -148,6 +148,7 @@ impl Js {
                     ),
                     my_ext,
                 ],
+                create_params: Some(create_params),
                 ..Default::default()
             });
 
@@ -166,21 +167,77 @@ impl Js {
             js_runtime
         };
 
-        // Add a callback that expands our heap by 1.25 each time
-        // it is invoked. There is no limit, since we rely on the
-        // execution environment (OS) to provide that.
+        self.add_near_heap_limit_callback(&mut js_runtime);
+        js_runtime
+    }
+
+    /// Expand the heap according to our [`HeapPolicy`] each time it is about to run out of memory.
+    fn add_near_heap_limit_callback(&self, js_runtime: &mut JsRuntime) {
         let name = self.name.clone();
+        let heap_policy = self.heap_policy;
+        let heap_expansions = self.heap_expansions.clone();
+        let heap_limit_reached = self.heap_limit_reached.clone();
+        let isolate = js_runtime.v8_isolate().thread_safe_handle();
+
         js_runtime.add_near_heap_limit_callback(move |current, initial| {
-            let new = current * 5 / 4;
-            tracing::info!(
-                "deno heap expansion({}): initial: {}, current: {}, new: {}",
-                name,
-                initial,
-                current,
-                new
-            );
-            new
+            match heap_policy.next_limit(current) {
+                NextHeapLimit::Grow(new) => {
+                    heap_expansions.fetch_add(1, Ordering::Relaxed);
+                    tracing::info!(
+                        "deno heap expansion({}): initial: {}, current: {}, new: {}",
+                        name,
+                        initial,
+                        current,
+                        new
+                    );
+                    new
+                }
+                NextHeapLimit::Exceeded => match heap_policy.on_max_limit {
+                    HeapLimitAction::Fail => {
+                        tracing::error!(
+                            "deno heap limit reached({}): current: {}, interrupting the running request",
+                            name,
+                            current
+                        );
+                        heap_limit_reached.store(true, Ordering::SeqCst);
+                        isolate.terminate_execution();
+                        // The running request needs some memory to unwind
+                        current + current / 4
+                    }
+                    HeapLimitAction::Abort => {
+                        tracing::error!(
+                            "deno heap limit reached({}): current: {}, aborting",
+                            name,
+                            current
+                        );
+                        current
+                    }
+                },
+            }
         });
-        js_runtime
+    }
+
+    /// Whether the heap reached its ceiling since the last time we checked.
+    pub(crate) fn take_heap_limit_reached(&self) -> bool {
+        self.heap_limit_reached.swap(false, Ordering::SeqCst)
+    }
+
+    /// The error of a request that was interrupted because the heap reached its ceiling.
+    pub(crate) fn heap_limit_exceeded(&self) -> Error {
+        Error::HeapLimitExceeded {
+            // Without a ceiling, the heap can only be out of memory if it can't grow at all.
+            limit_mb: self
+                .heap_policy
+                .max_limit_mb
+                .unwrap_or(self.heap_policy.initial_limit_mb),
+        }
+    }
+
+    /// Bring the heap limit back to the ceiling, after a request was interrupted for exceeding it.
+    pub(crate) fn reset_heap_limit(&self, js_runtime: &mut JsRuntime) {
+        if let Some(max_limit) = self.heap_policy.max_limit() {
+            js_runtime.remove_near_heap_limit_callback(max_limit);
+            self.add_near_heap_limit_callback(js_runtime);
+        }
     }
 }
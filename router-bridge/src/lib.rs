@@ -6,8 +6,22 @@
 #![deny(missing_debug_implementations, nonstandard_style)]
 #![warn(missing_docs, future_incompatible, unreachable_pub, rust_2018_idioms)]
 pub mod api_schema;
+pub mod authorization;
+pub mod demand_control;
 pub mod error;
+pub mod heap;
+pub mod impact;
 pub mod introspect;
 mod js;
+pub mod persisted_queries;
+pub mod plan_cache;
+pub mod plan_cost;
+pub mod plan_graph;
+pub mod plan_types;
 pub mod planner;
+mod pool;
+pub mod queue;
+pub mod usage_aggregate;
+pub mod usage_report;
+pub mod variables;
 mod worker;
@@ -0,0 +1,132 @@
+/*!
+# Persisted query manifests: the safelist of operations a router accepts.
+
+A manifest can be loaded in a [`Planner`](crate::planner::Planner), which validates every operation
+against its schema, and against the schemas it is updated to, and can plan operations by their id.
+*/
+
+use crate::planner::PlanErrors;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::iter::FromIterator;
+
+const MANIFEST_FORMAT: &str = "apollo-persisted-query-manifest";
+const MANIFEST_VERSION: u32 = 1;
+
+/// Persisted operations, by id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PersistedQueryManifest {
+    operations: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ManifestFile {
+    format: String,
+    version: u32,
+    operations: Vec<ManifestOperation>,
+}
+
+#[derive(Deserialize)]
+struct ManifestOperation {
+    id: String,
+    body: String,
+}
+
+impl PersistedQueryManifest {
+    /// Create an empty manifest
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a manifest in the format generated by `@apollo/generate-persisted-query-manifest`
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let file: ManifestFile = serde_json::from_str(json)?;
+        if file.format != MANIFEST_FORMAT || file.version != MANIFEST_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "unsupported manifest format `{}` version {}, expected `{MANIFEST_FORMAT}` version {MANIFEST_VERSION}",
+                file.format, file.version
+            )));
+        }
+
+        Ok(file
+            .operations
+            .into_iter()
+            .map(|operation| (operation.id, operation.body))
+            .collect())
+    }
+
+    /// Add an operation to the manifest, and return the operation it replaces, if any
+    pub fn insert(&mut self, id: impl Into<String>, body: impl Into<String>) -> Option<String> {
+        self.operations.insert(id.into(), body.into())
+    }
+
+    /// The operation persisted under `id`
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.operations.get(id).map(String::as_str)
+    }
+
+    /// The number of operations in the manifest
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Return true if the manifest holds no operation
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// The ids and operations of the manifest, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.operations
+            .iter()
+            .map(|(id, body)| (id.as_str(), body.as_str()))
+    }
+}
+
+impl FromIterator<(String, String)> for PersistedQueryManifest {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self {
+            operations: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// The operations of a manifest that don't validate against a schema.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestValidation {
+    /// The validation errors, by operation id
+    pub invalid: BTreeMap<String, PlanErrors>,
+}
+
+impl ManifestValidation {
+    /// Return true if every operation of the manifest validates
+    pub fn is_valid(&self) -> bool {
+        self.invalid.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_from_json() {
+        let manifest = PersistedQueryManifest::from_json(
+            r#"{
+              "format": "apollo-persisted-query-manifest",
+              "version": 1,
+              "operations": [
+                { "id": "1", "name": "Me", "type": "query", "body": "query Me { me { id } }" }
+              ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(1, manifest.len());
+        assert_eq!(Some("query Me { me { id } }"), manifest.get("1"));
+
+        assert!(PersistedQueryManifest::from_json(
+            r#"{ "format": "something-else", "version": 1, "operations": [] }"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,333 @@
+/*!
+# A bounded, least recently used cache of query plans.
+*/
+
+use crate::planner::PlanOptions;
+use std::collections::{BTreeMap, HashMap};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Statistics about a [`Planner`](crate::planner::Planner)'s query plan cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlanCacheStats {
+    /// The number of plans that were served from the cache
+    pub hits: u64,
+    /// The number of plans that had to be computed by the query planner
+    pub misses: u64,
+    /// The number of plans currently in the cache
+    pub len: usize,
+    /// The maximum number of plans the cache can hold
+    pub capacity: usize,
+}
+
+/// The key a query plan is cached under.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PlanCacheKey {
+    schema_id: u64,
+    query: String,
+    operation_name: Option<String>,
+    options: PlanOptions,
+}
+
+impl PlanCacheKey {
+    pub(crate) fn new(
+        schema_id: u64,
+        query: &str,
+        operation_name: Option<&str>,
+        options: &PlanOptions,
+    ) -> Self {
+        Self {
+            schema_id,
+            query: normalize_query(query),
+            operation_name: operation_name.map(str::to_string),
+            options: options.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    payload: serde_json::Value,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_key: HashMap<PlanCacheKey, Entry>,
+    // Keys ordered from the least recently used to the most recently used
+    by_last_used: BTreeMap<u64, PlanCacheKey>,
+    clock: u64,
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+/// A query plan cache, shared by a planner and the planners it was updated to.
+///
+/// Plans are keyed on the schema id, so a planner never sees the plans of another schema.
+/// Only the raw payload of successful plans is kept.
+#[derive(Debug)]
+pub(crate) struct PlanCache {
+    capacity: NonZeroUsize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl PlanCache {
+    pub(crate) fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            capacity,
+            entries: Default::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &PlanCacheKey) -> Option<serde_json::Value> {
+        let mut entries = self.entries.lock().expect("plan cache lock poisoned");
+        let now = entries.tick();
+        let Entries {
+            by_key,
+            by_last_used,
+            ..
+        } = &mut *entries;
+
+        match by_key.get_mut(key) {
+            Some(entry) => {
+                by_last_used.remove(&entry.last_used);
+                by_last_used.insert(now, key.clone());
+                entry.last_used = now;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.payload.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn insert(&self, key: PlanCacheKey, payload: serde_json::Value) {
+        let mut entries = self.entries.lock().expect("plan cache lock poisoned");
+        let now = entries.tick();
+
+        if let Some(previous) = entries.by_key.remove(&key) {
+            entries.by_last_used.remove(&previous.last_used);
+        }
+
+        while entries.by_key.len() >= self.capacity.get() {
+            match entries.by_last_used.pop_first() {
+                Some((_, evicted)) => {
+                    entries.by_key.remove(&evicted);
+                }
+                None => break,
+            }
+        }
+
+        entries.by_last_used.insert(now, key.clone());
+        entries.by_key.insert(
+            key,
+            Entry {
+                payload,
+                last_used: now,
+            },
+        );
+    }
+
+    /// Remove every plan computed against `schema_id`.
+    pub(crate) fn invalidate(&self, schema_id: u64) {
+        let mut entries = self.entries.lock().expect("plan cache lock poisoned");
+        let Entries {
+            by_key,
+            by_last_used,
+            ..
+        } = &mut *entries;
+
+        by_key.retain(|key, entry| {
+            let keep = key.schema_id != schema_id;
+            if !keep {
+                by_last_used.remove(&entry.last_used);
+            }
+            keep
+        });
+    }
+
+    pub(crate) fn stats(&self) -> PlanCacheStats {
+        PlanCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: self
+                .entries
+                .lock()
+                .expect("plan cache lock poisoned")
+                .by_key
+                .len(),
+            capacity: self.capacity.get(),
+        }
+    }
+}
+
+/// Normalize a query so that documents that only differ by ignored tokens share the same key.
+///
+/// Whitespace, commas and comments are ignored tokens in GraphQL:
+/// runs of them are collapsed into a single space, and removed around punctuators.
+/// String values are kept as is.
+fn normalize_query(query: &str) -> String {
+    fn is_punctuator(c: char) -> bool {
+        "!$&()...:=@[]{}|".contains(c)
+    }
+
+    let mut normalized = String::with_capacity(query.len());
+    let mut pending_separator = false;
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() || c == ',' || c == '\u{feff}' => pending_separator = true,
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' || c == '\r' {
+                        break;
+                    }
+                }
+                pending_separator = true;
+            }
+            c => {
+                if pending_separator
+                    && !normalized.is_empty()
+                    && !is_punctuator(c)
+                    && !normalized.ends_with(is_punctuator)
+                {
+                    normalized.push(' ');
+                }
+                pending_separator = false;
+                normalized.push(c);
+
+                if c == '"' {
+                    let block = chars.peek() == Some(&'"') && {
+                        let mut lookahead = chars.clone();
+                        lookahead.next();
+                        lookahead.peek() == Some(&'"')
+                    };
+                    if block {
+                        normalized.push_str("\"\"");
+                        chars.next();
+                        chars.next();
+                    }
+                    copy_string(&mut chars, &mut normalized, block);
+                }
+            }
+        }
+    }
+
+    normalized
+}
+
+/// Copy a string value, up to and including its closing quote(s).
+fn copy_string(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    normalized: &mut String,
+    block: bool,
+) {
+    let mut quotes = 0;
+    while let Some(c) = chars.next() {
+        normalized.push(c);
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    normalized.push(escaped);
+                }
+                quotes = 0;
+            }
+            '"' => {
+                quotes += 1;
+                if !block || quotes == 3 {
+                    return;
+                }
+            }
+            _ => quotes = 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(schema_id: u64, query: &str) -> PlanCacheKey {
+        PlanCacheKey::new(schema_id, query, None, &PlanOptions::default())
+    }
+
+    #[test]
+    fn normalize_ignored_tokens() {
+        assert_eq!(
+            normalize_query(
+                "query  Me($a: Int, $b: Int) {\n  me(a: $a, b: $b) { id # the id\n name } }"
+            ),
+            normalize_query("query Me($a:Int $b:Int){me(a:$a b:$b){id name}}")
+        );
+        assert_eq!(
+            "query Me($a:Int$b:Int){me(a:$a b:$b){id name}}",
+            normalize_query("query Me($a:Int $b:Int){me(a:$a b:$b){id name}}")
+        );
+    }
+
+    #[test]
+    fn normalize_keeps_strings() {
+        assert_eq!(
+            r#"{search(text:"a ,  # b"){id}}"#,
+            normalize_query(r#"{ search(text: "a ,  # b") { id } }"#)
+        );
+        assert_eq!(
+            r#"{search(text:"a \"  b"){id}}"#,
+            normalize_query(r#"{ search(text: "a \"  b") { id } }"#)
+        );
+        assert_eq!(
+            "{search(text:\"\"\"a \"\" ,\n  b\"\"\"){id}}",
+            normalize_query("{ search(text: \"\"\"a \"\" ,\n  b\"\"\") { id } }")
+        );
+    }
+
+    #[test]
+    fn least_recently_used_plans_are_evicted() {
+        let cache = PlanCache::new(NonZeroUsize::new(2).unwrap());
+        cache.insert(key(1, "{ a }"), serde_json::json!("a"));
+        cache.insert(key(1, "{ b }"), serde_json::json!("b"));
+
+        // `{ a }` is now the most recently used plan
+        assert_eq!(Some(serde_json::json!("a")), cache.get(&key(1, "{a}")));
+
+        cache.insert(key(1, "{ c }"), serde_json::json!("c"));
+        assert_eq!(None, cache.get(&key(1, "{ b }")));
+        assert_eq!(Some(serde_json::json!("a")), cache.get(&key(1, "{ a }")));
+        assert_eq!(Some(serde_json::json!("c")), cache.get(&key(1, "{ c }")));
+
+        assert_eq!(
+            PlanCacheStats {
+                hits: 3,
+                misses: 1,
+                len: 2,
+                capacity: 2,
+            },
+            cache.stats()
+        );
+    }
+
+    #[test]
+    fn invalidate_schema() {
+        let cache = PlanCache::new(NonZeroUsize::new(10).unwrap());
+        cache.insert(key(1, "{ a }"), serde_json::json!("a"));
+        cache.insert(key(2, "{ a }"), serde_json::json!("a2"));
+
+        cache.invalidate(1);
+        assert_eq!(None, cache.get(&key(1, "{ a }")));
+        assert_eq!(Some(serde_json::json!("a2")), cache.get(&key(2, "{ a }")));
+        assert_eq!(1, cache.stats().len);
+    }
+}
@@ -0,0 +1,264 @@
+/*!
+# Static cost estimation of query plans.
+
+The cost of a plan is estimated from its shape alone, before it is executed,
+so that expensive operations can be rejected or throttled against a budget.
+*/
+
+use crate::plan_types::{FetchNode, PlanNode, QueryPlan};
+use std::collections::{BTreeMap, HashMap};
+
+/// The weights of each aspect of a plan in its estimated cost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostWeights {
+    /// The cost of each fetch
+    pub fetch: f64,
+    /// The additional cost of each `_entities` fetch
+    pub entity_fetch: f64,
+    /// The cost of each fetch on the critical path of the plan
+    pub sequential_depth: f64,
+    /// The cost of each branch of the widest `Parallel` node
+    pub parallel_fan_out: f64,
+    /// Multipliers for the fetch costs of specific subgraphs. Subgraphs that are not listed use 1.0
+    pub subgraphs: HashMap<String, f64>,
+}
+
+impl Default for CostWeights {
+    fn default() -> Self {
+        Self {
+            fetch: 1.0,
+            entity_fetch: 1.0,
+            sequential_depth: 1.0,
+            parallel_fan_out: 0.0,
+            subgraphs: HashMap::new(),
+        }
+    }
+}
+
+/// The estimated cost of a query plan.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlanCost {
+    /// The number of fetches in the plan
+    pub fetches: usize,
+    /// The number of fetches on the longest chain of fetches that must run one after the other
+    pub sequential_depth: usize,
+    /// The largest number of branches of a `Parallel` node
+    pub parallel_fan_out: usize,
+    /// The number of `_entities` fetches to each subgraph
+    pub entity_fetches: BTreeMap<String, usize>,
+    /// The weighted sum of the above, to compare against a budget
+    pub estimate: f64,
+}
+
+impl QueryPlan {
+    /// Estimate the cost of executing this plan, according to `weights`.
+    ///
+    /// Every branch of a `Condition`, and every deferred part of a `@defer`ed operation,
+    /// is assumed to be executed.
+    pub fn estimate_cost(&self, weights: &CostWeights) -> PlanCost {
+        let mut fetches = 0;
+        let mut entity_fetches = BTreeMap::new();
+        let mut estimate = 0.0;
+        let mut sequential_depth = 0;
+        let mut parallel_fan_out = 0;
+
+        if let Some(node) = &self.node {
+            sequential_depth = visit(node, &mut |fetch| {
+                fetches += 1;
+                let multiplier = weights
+                    .subgraphs
+                    .get(&fetch.service_name)
+                    .copied()
+                    .unwrap_or(1.0);
+                estimate += weights.fetch * multiplier;
+                if fetch.is_entity_fetch() {
+                    *entity_fetches
+                        .entry(fetch.service_name.clone())
+                        .or_default() += 1;
+                    estimate += weights.entity_fetch * multiplier;
+                }
+            });
+            parallel_fan_out = fan_out(node);
+        }
+
+        PlanCost {
+            fetches,
+            sequential_depth,
+            parallel_fan_out,
+            entity_fetches,
+            estimate: estimate
+                + weights.sequential_depth * sequential_depth as f64
+                + weights.parallel_fan_out * parallel_fan_out as f64,
+        }
+    }
+}
+
+/// Call `on_fetch` for every fetch of the plan, and return its sequential depth.
+fn visit(node: &PlanNode, on_fetch: &mut impl FnMut(&FetchNode)) -> usize {
+    match node {
+        PlanNode::Sequence { nodes } => nodes.iter().map(|node| visit(node, on_fetch)).sum(),
+        PlanNode::Parallel { nodes } => nodes
+            .iter()
+            .map(|node| visit(node, on_fetch))
+            .max()
+            .unwrap_or_default(),
+        PlanNode::Fetch(fetch) => {
+            on_fetch(fetch);
+            1
+        }
+        PlanNode::Flatten(flatten) => visit(&flatten.node, on_fetch),
+        PlanNode::Defer { primary, deferred } => {
+            let primary = primary
+                .node
+                .as_ref()
+                .map_or(0, |node| visit(node, on_fetch));
+            let deferred = deferred
+                .iter()
+                .filter_map(|deferred| deferred.node.as_ref())
+                .map(|node| visit(node, on_fetch))
+                .max()
+                .unwrap_or_default();
+            primary + deferred
+        }
+        PlanNode::Condition {
+            if_clause,
+            else_clause,
+            ..
+        } => if_clause
+            .iter()
+            .chain(else_clause.iter())
+            .map(|clause| visit(clause, on_fetch))
+            .max()
+            .unwrap_or_default(),
+        PlanNode::Subscription { primary, rest } => {
+            on_fetch(primary);
+            1 + rest.as_ref().map_or(0, |rest| visit(rest, on_fetch))
+        }
+    }
+}
+
+/// The largest number of branches of a `Parallel` node in the plan.
+fn fan_out(node: &PlanNode) -> usize {
+    match node {
+        PlanNode::Sequence { nodes } => nodes.iter().map(fan_out).max().unwrap_or_default(),
+        PlanNode::Parallel { nodes } => nodes
+            .iter()
+            .map(fan_out)
+            .max()
+            .unwrap_or_default()
+            .max(nodes.len()),
+        PlanNode::Fetch(_) => 0,
+        PlanNode::Flatten(flatten) => fan_out(&flatten.node),
+        PlanNode::Defer { primary, deferred } => primary
+            .node
+            .iter()
+            .chain(
+                deferred
+                    .iter()
+                    .filter_map(|deferred| deferred.node.as_ref()),
+            )
+            .map(|node| fan_out(node))
+            .max()
+            .unwrap_or_default(),
+        PlanNode::Condition {
+            if_clause,
+            else_clause,
+            ..
+        } => if_clause
+            .iter()
+            .chain(else_clause.iter())
+            .map(|clause| fan_out(clause))
+            .max()
+            .unwrap_or_default(),
+        PlanNode::Subscription { rest, .. } => rest.as_ref().map_or(0, |rest| fan_out(rest)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan_types::{FlattenNode, OperationKind, Selection};
+
+    fn fetch(service_name: &str, entity: bool) -> PlanNode {
+        PlanNode::Fetch(FetchNode {
+            service_name: service_name.to_string(),
+            id: None,
+            variable_usages: Vec::new(),
+            requires: entity.then(Vec::<Selection>::new),
+            operation: "{me{id}}".to_string(),
+            operation_name: None,
+            operation_kind: OperationKind::Query,
+            input_rewrites: None,
+            output_rewrites: None,
+            context_rewrites: None,
+        })
+    }
+
+    fn flatten(node: PlanNode) -> PlanNode {
+        PlanNode::Flatten(FlattenNode {
+            path: vec!["me".to_string()],
+            node: Box::new(node),
+        })
+    }
+
+    // accounts, then reviews and inventory in parallel, then products after reviews
+    fn plan() -> QueryPlan {
+        QueryPlan {
+            node: Some(PlanNode::Sequence {
+                nodes: vec![
+                    fetch("accounts", false),
+                    PlanNode::Parallel {
+                        nodes: vec![
+                            PlanNode::Sequence {
+                                nodes: vec![
+                                    flatten(fetch("reviews", true)),
+                                    flatten(fetch("products", true)),
+                                ],
+                            },
+                            flatten(fetch("inventory", true)),
+                        ],
+                    },
+                ],
+            }),
+        }
+    }
+
+    #[test]
+    fn plan_cost() {
+        let cost = plan().estimate_cost(&CostWeights::default());
+        assert_eq!(4, cost.fetches);
+        assert_eq!(3, cost.sequential_depth);
+        assert_eq!(2, cost.parallel_fan_out);
+        assert_eq!(
+            BTreeMap::from([
+                ("inventory".to_string(), 1),
+                ("products".to_string(), 1),
+                ("reviews".to_string(), 1),
+            ]),
+            cost.entity_fetches
+        );
+        // 4 fetches, 3 entity fetches, and a depth of 3
+        assert_eq!(10.0, cost.estimate);
+    }
+
+    #[test]
+    fn weighted_plan_cost() {
+        let weights = CostWeights {
+            fetch: 2.0,
+            entity_fetch: 0.0,
+            sequential_depth: 0.0,
+            parallel_fan_out: 10.0,
+            subgraphs: HashMap::from([("accounts".to_string(), 5.0)]),
+        };
+        // accounts costs 2 * 5, the other fetches 2 each, and the fan out 2 * 10
+        assert_eq!(36.0, plan().estimate_cost(&weights).estimate);
+    }
+
+    #[test]
+    fn empty_plan_cost() {
+        assert_eq!(
+            PlanCost::default(),
+            QueryPlan { node: None }.estimate_cost(&CostWeights::default())
+        );
+    }
+}
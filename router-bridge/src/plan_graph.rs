@@ -0,0 +1,475 @@
+/*!
+# Render query plans as Graphviz DOT and Mermaid flowcharts.
+
+Fetches are drawn as boxes labelled with their subgraph. Edges follow the order in which the
+nodes are executed: the steps of a `Sequence` are chained, the branches of a `Parallel` node
+fork and join. `Flatten` nodes carry the response path their fetch is merged at, and deferred
+nodes carry their `@defer` label and depend on the fetches they need.
+*/
+
+use crate::plan_types::{PlanNode, QueryPlan};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+impl QueryPlan {
+    /// Render the plan as a Graphviz DOT digraph.
+    pub fn to_dot(&self) -> String {
+        Graph::new(self).to_dot()
+    }
+
+    /// Render the plan as a Mermaid flowchart.
+    pub fn to_mermaid(&self) -> String {
+        Graph::new(self).to_mermaid()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Fetch,
+    Flatten,
+    Defer,
+    Condition,
+}
+
+#[derive(Debug)]
+struct Node {
+    label: String,
+    shape: Shape,
+}
+
+#[derive(Debug)]
+struct Edge {
+    from: usize,
+    to: usize,
+    label: Option<String>,
+}
+
+/// A plan laid out as nodes and edges, shared by both output formats.
+#[derive(Debug, Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    // The graph node of each fetch that has an id, for deferred nodes to depend on
+    fetch_ids: HashMap<String, usize>,
+    // Deferred nodes and the fetch ids they depend on, resolved once every fetch is laid out
+    depends: Vec<(usize, String)>,
+}
+
+/// The nodes a plan node is entered through, and the nodes it is exited from.
+///
+/// Both are empty for plan nodes that have no graph node, such as an empty `Sequence`.
+struct Ends {
+    entries: Vec<usize>,
+    exits: Vec<usize>,
+}
+
+impl Ends {
+    /// The exits of a node nested in `parent`, which is exited from directly if the nested node is empty.
+    fn exits_or(self, parent: usize) -> Vec<usize> {
+        if self.exits.is_empty() {
+            vec![parent]
+        } else {
+            self.exits
+        }
+    }
+}
+
+impl Graph {
+    fn new(plan: &QueryPlan) -> Self {
+        let mut graph = Self::default();
+        if let Some(node) = &plan.node {
+            graph.lay_out(node);
+        }
+
+        for (deferred, id) in std::mem::take(&mut graph.depends) {
+            if let Some(fetch) = graph.fetch_ids.get(&id).copied() {
+                graph.edge(fetch, deferred, Some("depends".to_string()));
+            }
+        }
+        graph
+    }
+
+    fn node(&mut self, label: String, shape: Shape) -> usize {
+        self.nodes.push(Node { label, shape });
+        self.nodes.len() - 1
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: Option<String>) {
+        self.edges.push(Edge { from, to, label });
+    }
+
+    fn connect(&mut self, exits: &[usize], entries: &[usize], label: Option<&str>) {
+        for from in exits {
+            for to in entries {
+                self.edge(*from, *to, label.map(str::to_string));
+            }
+        }
+    }
+
+    fn lay_out(&mut self, node: &PlanNode) -> Ends {
+        match node {
+            PlanNode::Sequence { nodes } => {
+                let mut ends: Option<Ends> = None;
+                for node in nodes {
+                    let next = self.lay_out(node);
+                    // Empty steps are skipped, so that their neighbours are chained together
+                    if next.entries.is_empty() {
+                        continue;
+                    }
+                    ends = Some(match ends {
+                        None => next,
+                        Some(previous) => {
+                            self.connect(&previous.exits, &next.entries, None);
+                            Ends {
+                                entries: previous.entries,
+                                exits: next.exits,
+                            }
+                        }
+                    });
+                }
+                ends.unwrap_or(Ends {
+                    entries: Vec::new(),
+                    exits: Vec::new(),
+                })
+            }
+            PlanNode::Parallel { nodes } => {
+                let mut entries = Vec::new();
+                let mut exits = Vec::new();
+                for node in nodes {
+                    let ends = self.lay_out(node);
+                    entries.extend(ends.entries);
+                    exits.extend(ends.exits);
+                }
+                Ends { entries, exits }
+            }
+            PlanNode::Fetch(fetch) => {
+                let id = self.node(format!("Fetch({})", fetch.service_name), Shape::Fetch);
+                if let Some(fetch_id) = &fetch.id {
+                    self.fetch_ids.insert(fetch_id.clone(), id);
+                }
+                Ends {
+                    entries: vec![id],
+                    exits: vec![id],
+                }
+            }
+            PlanNode::Flatten(flatten) => {
+                let id = self.node(
+                    format!("Flatten({})", flatten.path.join(".")),
+                    Shape::Flatten,
+                );
+                let inner = self.lay_out(&flatten.node);
+                self.connect(&[id], &inner.entries, None);
+                Ends {
+                    entries: vec![id],
+                    exits: inner.exits_or(id),
+                }
+            }
+            PlanNode::Defer { primary, deferred } => {
+                let id = self.node("Defer".to_string(), Shape::Defer);
+                let primary_exits = match &primary.node {
+                    Some(node) => {
+                        let primary = self.lay_out(node);
+                        self.connect(&[id], &primary.entries, Some("primary"));
+                        primary.exits_or(id)
+                    }
+                    None => vec![id],
+                };
+
+                let mut exits = primary_exits.clone();
+                for deferred in deferred {
+                    let mut label = match &deferred.label {
+                        Some(label) => format!("Deferred({label})"),
+                        None => "Deferred".to_string(),
+                    };
+                    if !deferred.query_path.is_empty() {
+                        let _ = write!(label, " at {}", deferred.query_path.join("/"));
+                    }
+                    let deferred_id = self.node(label, Shape::Defer);
+                    // A deferred node waits for the fetches it depends on, or for the primary response
+                    if deferred.depends.is_empty() {
+                        self.connect(&primary_exits, &[deferred_id], None);
+                    }
+                    for depends in &deferred.depends {
+                        self.depends.push((deferred_id, depends.id.clone()));
+                    }
+
+                    match &deferred.node {
+                        Some(node) => {
+                            let inner = self.lay_out(node);
+                            self.connect(&[deferred_id], &inner.entries, None);
+                            exits.extend(inner.exits_or(deferred_id));
+                        }
+                        None => exits.push(deferred_id),
+                    }
+                }
+                Ends {
+                    entries: vec![id],
+                    exits,
+                }
+            }
+            PlanNode::Condition {
+                condition,
+                if_clause,
+                else_clause,
+            } => {
+                let id = self.node(format!("Condition(${condition})"), Shape::Condition);
+                let mut exits = Vec::new();
+                for (label, clause) in [("if", if_clause), ("else", else_clause)] {
+                    match clause {
+                        Some(clause) => {
+                            let inner = self.lay_out(clause);
+                            self.connect(&[id], &inner.entries, Some(label));
+                            exits.extend(inner.exits_or(id));
+                        }
+                        None => exits.push(id),
+                    }
+                }
+                exits.dedup();
+                Ends {
+                    entries: vec![id],
+                    exits,
+                }
+            }
+            PlanNode::Subscription { primary, rest } => {
+                let id = self.node(
+                    format!("Subscription({})", primary.service_name),
+                    Shape::Fetch,
+                );
+                let exits = match rest {
+                    Some(rest) => {
+                        let inner = self.lay_out(rest);
+                        self.connect(&[id], &inner.entries, Some("each event"));
+                        inner.exits_or(id)
+                    }
+                    None => vec![id],
+                };
+                Ends {
+                    entries: vec![id],
+                    exits,
+                }
+            }
+        }
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph QueryPlan {\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let shape = match node.shape {
+                Shape::Fetch => "box",
+                Shape::Flatten => "ellipse",
+                Shape::Defer => "hexagon",
+                Shape::Condition => "diamond",
+            };
+            let _ = writeln!(
+                dot,
+                "  n{id} [label=\"{}\", shape={shape}];",
+                escape_dot(&node.label)
+            );
+        }
+        for edge in &self.edges {
+            let _ = match &edge.label {
+                Some(label) => writeln!(
+                    dot,
+                    "  n{} -> n{} [label=\"{}\"];",
+                    edge.from,
+                    edge.to,
+                    escape_dot(label)
+                ),
+                None => writeln!(dot, "  n{} -> n{};", edge.from, edge.to),
+            };
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("flowchart TD\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let label = escape_mermaid(&node.label);
+            let _ = match node.shape {
+                Shape::Fetch => writeln!(mermaid, "  n{id}[\"{label}\"]"),
+                Shape::Flatten => writeln!(mermaid, "  n{id}([\"{label}\"])"),
+                Shape::Defer => writeln!(mermaid, "  n{id}{{{{\"{label}\"}}}}"),
+                Shape::Condition => writeln!(mermaid, "  n{id}{{\"{label}\"}}"),
+            };
+        }
+        for edge in &self.edges {
+            let _ = match &edge.label {
+                Some(label) => writeln!(
+                    mermaid,
+                    "  n{} -->|\"{}\"| n{}",
+                    edge.from,
+                    escape_mermaid(label),
+                    edge.to
+                ),
+                None => writeln!(mermaid, "  n{} --> n{}", edge.from, edge.to),
+            };
+        }
+        mermaid
+    }
+}
+
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plan_types::{
+        DeferredNode, Depends, FetchNode, FlattenNode, OperationKind, PlanNode, Primary, QueryPlan,
+    };
+
+    fn fetch(service_name: &str, id: Option<&str>) -> PlanNode {
+        PlanNode::Fetch(FetchNode {
+            service_name: service_name.to_string(),
+            id: id.map(str::to_string),
+            variable_usages: Vec::new(),
+            requires: None,
+            operation: "{me{id}}".to_string(),
+            operation_name: None,
+            operation_kind: OperationKind::Query,
+            input_rewrites: None,
+            output_rewrites: None,
+            context_rewrites: None,
+        })
+    }
+
+    fn plan() -> QueryPlan {
+        QueryPlan {
+            node: Some(PlanNode::Sequence {
+                nodes: vec![
+                    fetch("accounts", None),
+                    PlanNode::Parallel {
+                        nodes: vec![
+                            PlanNode::Flatten(FlattenNode {
+                                path: vec![
+                                    "me".to_string(),
+                                    "reviews".to_string(),
+                                    "@".to_string(),
+                                ],
+                                node: Box::new(fetch("reviews", None)),
+                            }),
+                            PlanNode::Condition {
+                                condition: "withProducts".to_string(),
+                                if_clause: Some(Box::new(fetch("products", None))),
+                                else_clause: None,
+                            },
+                        ],
+                    },
+                ],
+            }),
+        }
+    }
+
+    #[test]
+    fn render_dot() {
+        assert_eq!(
+            r#"digraph QueryPlan {
+  n0 [label="Fetch(accounts)", shape=box];
+  n1 [label="Flatten(me.reviews.@)", shape=ellipse];
+  n2 [label="Fetch(reviews)", shape=box];
+  n3 [label="Condition($withProducts)", shape=diamond];
+  n4 [label="Fetch(products)", shape=box];
+  n1 -> n2;
+  n3 -> n4 [label="if"];
+  n0 -> n1;
+  n0 -> n3;
+}
+"#,
+            plan().to_dot()
+        );
+    }
+
+    #[test]
+    fn render_mermaid() {
+        assert_eq!(
+            r#"flowchart TD
+  n0["Fetch(accounts)"]
+  n1(["Flatten(me.reviews.@)"])
+  n2["Fetch(reviews)"]
+  n3{"Condition($withProducts)"}
+  n4["Fetch(products)"]
+  n1 --> n2
+  n3 -->|"if"| n4
+  n0 --> n1
+  n0 --> n3
+"#,
+            plan().to_mermaid()
+        );
+    }
+
+    #[test]
+    fn render_defer() {
+        let plan = QueryPlan {
+            node: Some(PlanNode::Defer {
+                primary: Primary {
+                    subselection: None,
+                    node: Some(Box::new(fetch("accounts", Some("0")))),
+                },
+                deferred: vec![DeferredNode {
+                    depends: vec![Depends {
+                        id: "0".to_string(),
+                        defer_label: None,
+                    }],
+                    label: Some("slow".to_string()),
+                    query_path: vec!["me".to_string()],
+                    subselection: None,
+                    node: Some(Box::new(fetch("reviews", None))),
+                }],
+            }),
+        };
+
+        assert_eq!(
+            r#"flowchart TD
+  n0{{"Defer"}}
+  n1["Fetch(accounts)"]
+  n2{{"Deferred(slow) at me"}}
+  n3["Fetch(reviews)"]
+  n0 -->|"primary"| n1
+  n2 --> n3
+  n1 -->|"depends"| n2
+"#,
+            plan.to_mermaid()
+        );
+    }
+
+    #[test]
+    fn render_empty_sequence() {
+        let plan = QueryPlan {
+            node: Some(PlanNode::Sequence {
+                nodes: vec![
+                    fetch("accounts", None),
+                    PlanNode::Sequence { nodes: Vec::new() },
+                    PlanNode::Flatten(FlattenNode {
+                        path: vec!["me".to_string()],
+                        node: Box::new(PlanNode::Sequence { nodes: Vec::new() }),
+                    }),
+                    fetch("reviews", None),
+                ],
+            }),
+        };
+
+        assert_eq!(
+            r#"digraph QueryPlan {
+  n0 [label="Fetch(accounts)", shape=box];
+  n1 [label="Flatten(me)", shape=ellipse];
+  n2 [label="Fetch(reviews)", shape=box];
+  n0 -> n1;
+  n1 -> n2;
+}
+"#,
+            plan.to_dot()
+        );
+    }
+
+    #[test]
+    fn empty_plan() {
+        let plan = QueryPlan { node: None };
+        assert_eq!("digraph QueryPlan {\n}\n", plan.to_dot());
+        assert_eq!("flowchart TD\n", plan.to_mermaid());
+    }
+}
@@ -0,0 +1,405 @@
+/*!
+# A typed representation of the query plans built by `@apollo/query-planner`.
+
+These types mirror the `QueryPlan.ts` definitions of the bundled `@apollo/query-planner` package
+(see [`query_planner_version`]), and can be used as the `T` of a [`Planner`](crate::planner::Planner):
+
+```ignore
+let planner = Planner::<QueryPlanResult>::new(schema, QueryPlannerConfig::default()).await?;
+```
+*/
+
+use serde::{Deserialize, Serialize};
+
+/// The version of `@apollo/query-planner` these types are modeled after.
+///
+/// This is the version of the query planner bundled in this crate.
+pub fn query_planner_version() -> &'static str {
+    // The crate version carries the query planner version as build metadata, eg: `0.5.29+v2.8.3`
+    env!("CARGO_PKG_VERSION")
+        .split_once("+v")
+        .map(|(_, version)| version)
+        .unwrap_or("unknown")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// The payload returned by a successful query planning
+pub struct QueryPlanResult {
+    /// A human readable version of the query plan, if it could be generated
+    pub formatted_query_plan: Option<String>,
+    /// The query plan
+    pub query_plan: QueryPlan,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+/// A query plan
+pub struct QueryPlan {
+    /// The root node of the plan, if there is anything to fetch
+    #[serde(default)]
+    pub node: Option<PlanNode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+/// A node of a query plan
+pub enum PlanNode {
+    /// Nodes that must be executed one after the other
+    Sequence {
+        /// The nodes to execute in order
+        nodes: Vec<PlanNode>,
+    },
+    /// Nodes that can be executed concurrently
+    Parallel {
+        /// The nodes to execute
+        nodes: Vec<PlanNode>,
+    },
+    /// A fetch to a subgraph
+    Fetch(FetchNode),
+    /// Merge the result of a node at a given path of the response
+    Flatten(FlattenNode),
+    /// A `@defer`ed operation
+    Defer {
+        /// The part of the response that is not deferred
+        primary: Primary,
+        /// The deferred parts of the response
+        deferred: Vec<DeferredNode>,
+    },
+    /// A branching on the value of a boolean variable
+    #[serde(rename_all = "camelCase")]
+    Condition {
+        /// The name of the variable the condition depends on
+        condition: String,
+        /// The node to execute if the variable is `true`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        if_clause: Option<Box<PlanNode>>,
+        /// The node to execute if the variable is `false`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        else_clause: Option<Box<PlanNode>>,
+    },
+    /// A subscription
+    Subscription {
+        /// The fetch that sets up the subscription
+        primary: FetchNode,
+        /// The nodes executed for each event of the subscription
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rest: Option<Box<PlanNode>>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// The kind of GraphQL operation
+pub enum OperationKind {
+    /// A query
+    Query,
+    /// A mutation
+    Mutation,
+    /// A subscription
+    Subscription,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// A fetch to a subgraph
+pub struct FetchNode {
+    /// The name of the subgraph to fetch from
+    pub service_name: String,
+    /// An identifier for the fetch, used by deferred nodes to depend on it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The variables of the client operation used by the subgraph operation
+    #[serde(default)]
+    pub variable_usages: Vec<String>,
+    /// The data that must be sent as `representations` for an entity fetch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<Vec<Selection>>,
+    /// The operation sent to the subgraph
+    pub operation: String,
+    /// The name of the operation sent to the subgraph
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation_name: Option<String>,
+    /// The kind of the operation sent to the subgraph
+    pub operation_kind: OperationKind,
+    /// Rewrites to apply to the representations before sending them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_rewrites: Option<Vec<DataRewrite>>,
+    /// Rewrites to apply to the subgraph response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_rewrites: Option<Vec<DataRewrite>>,
+    /// Rewrites used to pass `@fromContext` values as variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_rewrites: Option<Vec<DataRewrite>>,
+}
+
+impl FetchNode {
+    /// Return true if the fetch is an `_entities` fetch
+    pub fn is_entity_fetch(&self) -> bool {
+        self.requires.is_some()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// Merge the result of a node at a given path of the response
+pub struct FlattenNode {
+    /// The path in the response, `@` stands for every element of a list
+    pub path: Vec<String>,
+    /// The node to execute
+    pub node: Box<PlanNode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// The part of a `@defer`ed operation that is not deferred
+pub struct Primary {
+    /// The selection set of the primary response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subselection: Option<String>,
+    /// The node to execute for the primary response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<Box<PlanNode>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// A deferred part of the response
+pub struct DeferredNode {
+    /// The fetches this deferred node depends on
+    pub depends: Vec<Depends>,
+    /// The label of the `@defer` directive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// The path of the deferred fields in the response
+    pub query_path: Vec<String>,
+    /// The selection set of the deferred response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subselection: Option<String>,
+    /// The node to execute for the deferred response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<Box<PlanNode>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A dependency of a deferred node
+pub struct Depends {
+    /// The id of the fetch the deferred node depends on
+    pub id: String,
+    /// The label of the `@defer` that fetch belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defer_label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind")]
+/// A selection in the `requires` of a fetch
+pub enum Selection {
+    /// A field
+    Field(Field),
+    /// An inline fragment
+    InlineFragment(InlineFragment),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// A field selection
+pub struct Field {
+    /// The alias of the field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// The name of the field
+    pub name: String,
+    /// The subselections of the field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selections: Option<Vec<Selection>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// An inline fragment selection
+pub struct InlineFragment {
+    /// The type condition of the fragment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_condition: Option<String>,
+    /// The subselections of the fragment
+    pub selections: Vec<Selection>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+/// A rewrite applied to the data sent to, or received from a subgraph
+pub enum DataRewrite {
+    /// Set the value at the given path
+    #[serde(rename_all = "camelCase")]
+    ValueSetter {
+        /// The path of the value to set
+        path: Vec<String>,
+        /// The value to set
+        set_value_to: serde_json::Value,
+    },
+    /// Rename the key at the given path
+    #[serde(rename_all = "camelCase")]
+    KeyRenamer {
+        /// The path of the key to rename
+        path: Vec<String>,
+        /// The new name of the key
+        rename_key_to: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planner::{IncrementalDeliverySupport, PlanOptions, Planner, QueryPlannerConfig};
+
+    const SCHEMA: &str = include_str!("testdata/schema.graphql");
+    const QUERY: &str = include_str!("testdata/query.graphql");
+    const QUERY2: &str = include_str!("testdata/query2.graphql");
+    const NAMED_QUERY: &str = include_str!("testdata/named_query.graphql");
+    const PROGRESSIVE_OVERRIDE: &str = include_str!("testdata/progressive_override.graphql");
+    const TYPED_CONDITIONS: &str = include_str!("testdata/typed_conditions.graphql");
+
+    async fn assert_round_trip(
+        schema: &str,
+        query: &str,
+        config: QueryPlannerConfig,
+        options: PlanOptions,
+    ) {
+        let planner = Planner::<serde_json::Value>::new(schema.to_string(), config)
+            .await
+            .unwrap();
+
+        let raw = planner
+            .plan(query.to_string(), None, options)
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .data;
+
+        let typed: QueryPlanResult = serde_json::from_value(raw.clone()).unwrap();
+        assert!(typed.query_plan.node.is_some());
+        assert_eq!(raw, serde_json::to_value(&typed).unwrap());
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        for query in [QUERY, QUERY2, NAMED_QUERY] {
+            assert_round_trip(
+                SCHEMA,
+                query,
+                QueryPlannerConfig::default(),
+                PlanOptions::default(),
+            )
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trip_progressive_override() {
+        assert_round_trip(
+            PROGRESSIVE_OVERRIDE,
+            "{ t { a } }",
+            QueryPlannerConfig::default(),
+            PlanOptions {
+                override_conditions: vec!["foo".to_string()],
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn round_trip_defer() {
+        assert_round_trip(
+            SCHEMA,
+            "query Me($shouldDefer: Boolean!) { me { id ... @defer(if: $shouldDefer) { name { first } reviews { body } } } }",
+            QueryPlannerConfig {
+                incremental_delivery: Some(IncrementalDeliverySupport {
+                    enable_defer: Some(true),
+                }),
+                ..Default::default()
+            },
+            PlanOptions::default(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn round_trip_type_conditioned_fetching() {
+        assert_round_trip(
+            TYPED_CONDITIONS,
+            "query Search($movieParams: String) { search { __typename ... on MovieResult { id sections { ... on EntityCollectionSection { id artwork(params: $movieParams) } } } } }",
+            QueryPlannerConfig {
+                type_conditioned_fetching: true,
+                ..Default::default()
+            },
+            PlanOptions::default(),
+        )
+        .await;
+    }
+
+    #[test]
+    fn query_planner_version_is_set() {
+        assert_ne!("unknown", query_planner_version());
+    }
+
+    #[test]
+    fn deserialize_condition_and_subscription() {
+        let raw = serde_json::json!({
+            "kind": "QueryPlan",
+            "node": {
+                "kind": "Subscription",
+                "primary": {
+                    "serviceName": "reviews",
+                    "variableUsages": [],
+                    "operation": "subscription{reviewAdded{id}}",
+                    "operationKind": "subscription"
+                },
+                "rest": {
+                    "kind": "Condition",
+                    "condition": "withAuthor",
+                    "ifClause": {
+                        "kind": "Flatten",
+                        "path": ["reviewAdded"],
+                        "node": {
+                            "kind": "Fetch",
+                            "serviceName": "accounts",
+                            "requires": [{
+                                "kind": "InlineFragment",
+                                "typeCondition": "Review",
+                                "selections": [
+                                    { "kind": "Field", "name": "__typename" },
+                                    { "kind": "Field", "name": "id" }
+                                ]
+                            }],
+                            "variableUsages": [],
+                            "operation": "query($representations:[_Any!]!){_entities(representations:$representations){...on Review{author{name}}}}",
+                            "operationKind": "query",
+                            "inputRewrites": [{
+                                "kind": "ValueSetter",
+                                "path": ["... on Review", "__typename"],
+                                "setValueTo": "Review"
+                            }]
+                        }
+                    }
+                }
+            }
+        });
+
+        let plan: QueryPlan = serde_json::from_value(raw.clone()).unwrap();
+        match &plan.node {
+            Some(PlanNode::Subscription { primary, rest }) => {
+                assert_eq!(OperationKind::Subscription, primary.operation_kind);
+                assert!(matches!(
+                    rest.as_deref(),
+                    Some(PlanNode::Condition {
+                        if_clause: Some(_),
+                        else_clause: None,
+                        ..
+                    })
+                ));
+            }
+            other => panic!("expected a subscription node, got {other:?}"),
+        }
+        assert_eq!(raw, serde_json::to_value(&plan).unwrap());
+    }
+}
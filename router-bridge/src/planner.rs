@@ -6,15 +6,26 @@ use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::marker::PhantomData;
+use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::time::Duration;
 
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
+use crate::authorization::AuthorizationRequirements;
+use crate::demand_control::{DemandCost, DemandCostOptions};
+use crate::heap::{HeapPolicy, HeapStatistics};
 use crate::introspect::IntrospectionResponse;
-use crate::worker::JsWorker;
+use crate::persisted_queries::{ManifestValidation, PersistedQueryManifest};
+use crate::plan_cache::{PlanCache, PlanCacheKey, PlanCacheStats};
+use crate::plan_types::OperationKind;
+use crate::pool::JsWorkerPool;
+use crate::queue::{QueuePolicy, QueueStatistics};
+use crate::variables::CoercedVariables;
 
 // ------------------------------------
 
@@ -324,6 +335,12 @@ pub struct PlanResult<T> {
     pub usage_reporting: UsageReporting,
     /// The errors if the query failed
     pub errors: Option<Vec<PlanError>>,
+    /// How long planning took, if [`QueryPlannerConfig::planning_statistics`] is enabled
+    ///
+    /// Durations are measured on a monotonic clock, with a microsecond resolution.
+    /// Plans served from the plan cache weren't computed for this request, so they have none.
+    #[serde(default)]
+    pub statistics: Option<PlanningStatistics>,
 }
 
 /// The payload if the plan_worker invocation succeeded
@@ -334,6 +351,90 @@ pub struct PlanSuccess<T> {
     /// Usage reporting related data such as the
     /// operation signature and referenced fields
     pub usage_reporting: UsageReporting,
+    /// How long planning took, if [`QueryPlannerConfig::planning_statistics`] is enabled
+    pub statistics: Option<PlanningStatistics>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// How long each phase of [`Planner::plan`] took, and how many plans the query planner evaluated
+pub struct PlanningStatistics {
+    /// The duration of each phase
+    pub phases: PlanningPhases,
+    /// The number of plans the query planner evaluated,
+    /// which is bounded by [`QueryPlannerDebugConfig::max_evaluated_plans`].
+    ///
+    /// Missing if planning stopped before the query planner ran.
+    pub evaluated_plan_count: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// The duration of each phase of [`Planner::plan`], in order.
+///
+/// Durations are measured on a monotonic clock, with a microsecond resolution.
+/// A phase is missing if planning stopped before it, such as when the operation doesn't validate.
+pub struct PlanningPhases {
+    /// Parsing the operation, including the check of [`OperationLimits::max_tokens`]
+    #[serde(default, deserialize_with = "deserialize_millis")]
+    pub parsing: Option<Duration>,
+    /// Validating the operation, including the checks of the other [`OperationLimits`]
+    #[serde(default, deserialize_with = "deserialize_millis")]
+    pub validation: Option<Duration>,
+    /// Building the operation against the supergraph, with `operationFromDocument`
+    #[serde(default, deserialize_with = "deserialize_millis")]
+    pub operation_from_document: Option<Duration>,
+    /// Computing the usage reporting signature and referenced fields
+    #[serde(default, deserialize_with = "deserialize_millis")]
+    pub usage_reporting: Option<Duration>,
+    /// Building the query plan, with `buildQueryPlan`
+    #[serde(default, deserialize_with = "deserialize_millis")]
+    pub query_planning: Option<Duration>,
+    /// Formatting the query plan, with `prettyFormatQueryPlan`
+    #[serde(default, deserialize_with = "deserialize_millis")]
+    pub formatting: Option<Duration>,
+}
+
+fn deserialize_millis<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let millis: Option<f64> = Deserialize::deserialize(deserializer)?;
+    Ok(millis.map(|millis| Duration::from_secs_f64(millis.max(0.0) / 1000.0)))
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// The operation of a query that passed [`Planner::validate`]
+pub struct ValidatedOperation {
+    /// The name of the operation, if it has one
+    pub operation_name: Option<String>,
+    /// Whether the operation is a query, a mutation or a subscription
+    pub operation_kind: OperationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An operation in a normalized, executable form, see [`Planner::normalized_operation`]
+pub struct NormalizedOperation {
+    /// The normalized operation document
+    pub document: String,
+    /// The SHA-256 hash of `document`, as a lowercase hexadecimal string
+    pub hash: String,
+}
+
+impl NormalizedOperation {
+    fn new(document: String) -> Self {
+        let hash = Sha256::digest(document.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Self { document, hash }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct NormalizedDocument {
+    document: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -381,6 +482,7 @@ where
             Ok(PlanSuccess {
                 data,
                 usage_reporting,
+                statistics: self.statistics,
             })
         } else {
             let errors = Arc::new(self.errors.unwrap_or_else(|| {
@@ -399,13 +501,19 @@ where
 }
 
 /// A Deno worker backed query Planner.
-
+///
+/// The planner can be backed by a pool of workers, see [`PlannerOptions`].
 pub struct Planner<T>
 where
     T: DeserializeOwned + Send + Debug + 'static,
 {
-    worker: Arc<JsWorker>,
+    workers: Arc<JsWorkerPool>,
     schema_id: u64,
+    plan_cache: Option<Arc<PlanCache>>,
+    persisted_queries: Option<Arc<PersistedQueryManifest>>,
+    persisted_query_validation: Option<ManifestValidation>,
+    /// Set once the planner went through [`Planner::shutdown`], so dropping it has nothing left to do
+    shut_down: bool,
     t: PhantomData<T>,
 }
 
@@ -416,6 +524,7 @@ where
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Planner")
             .field("schema_id", &self.schema_id)
+            .field("pool_size", &self.workers.size())
             .finish()
     }
 }
@@ -424,61 +533,84 @@ impl<T> Planner<T>
 where
     T: DeserializeOwned + Send + Debug + 'static,
 {
-    /// Instantiate a `Planner` from a schema string
+    /// Instantiate a `Planner` from a schema string, backed by JavaScript workers set up with
+    /// the default [`PlannerOptions`]
     pub async fn new(
         schema: String,
         config: QueryPlannerConfig,
     ) -> Result<Self, Vec<PlannerError>> {
-        let schema_id: u64 = rand::random();
-        let worker = JsWorker::new(include_str!("../bundled/plan_worker.js"));
-        let worker_is_set_up = worker
-            .request::<PlanCmd, BridgeSetupResult<serde_json::Value>>(PlanCmd::UpdateSchema {
-                schema,
-                config,
-                schema_id,
-            })
-            .await
-            .map_err(|e| {
-                vec![WorkerError {
-                    name: Some("planner setup error".to_string()),
-                    message: Some(e.to_string()),
-                    stack: None,
-                    extensions: None,
-                    locations: Default::default(),
-                }
-                .into()]
-            });
+        Self::new_with_options(schema, config, PlannerOptions::default()).await
+    }
 
-        // Both cases below the mean schema update failed.
-        // We need to pay attention here.
-        // returning early will drop the worker, which will join the jsruntime thread.
-        // however the event loop will run for ever. We need to let the worker know it needs to exit,
-        // before we drop the worker
-        match worker_is_set_up {
-            Err(setup_error) => {
-                let _ = worker
-                    .request::<PlanCmd, serde_json::Value>(PlanCmd::Exit { schema_id })
-                    .await;
-                return Err(setup_error);
-            }
-            Ok(setup) => {
-                if let Some(error) = setup.errors {
-                    let _ = worker.send(None, PlanCmd::Exit { schema_id }).await;
-                    return Err(error);
-                }
-            }
-        }
+    /// Instantiate a `Planner` from a schema string, backed by JavaScript workers set up with `options`
+    ///
+    /// The schema is loaded in every worker of the pool,
+    /// and planning requests are spread across the workers.
+    /// The workers are shared with the planners this one is updated to, so they keep these options.
+    pub async fn new_with_options(
+        schema: String,
+        config: QueryPlannerConfig,
+        options: PlannerOptions,
+    ) -> Result<Self, Vec<PlannerError>> {
+        let PlannerOptions {
+            pool_size,
+            queue_policy,
+            heap_policy,
+        } = options;
+        heap_policy
+            .validate()
+            .map_err(|e| setup_error(crate::error::Error::InvalidHeapPolicy(e.to_string())))?;
 
-        let worker = Arc::new(worker);
+        let schema_id: u64 = rand::random();
+        let workers = JsWorkerPool::new(
+            include_str!("../bundled/plan_worker.js"),
+            pool_size,
+            heap_policy,
+            queue_policy,
+        );
+        let workers_are_set_up = Self::set_up_schema(&workers, schema, config, schema_id).await;
+
+        // If the schema update failed on any of the workers, we need to pay attention here.
+        // returning early will drop the workers, which exit in the background once they processed
+        // their queue. We let the workers that accepted the schema know they can drop it, before we drop them
+        if let Err(setup_error) = workers_are_set_up {
+            workers.remove_restart_command(&schema_id.to_string());
+            workers.notify(PlanCmd::Exit { schema_id }).await;
+            return Err(setup_error);
+        }
 
         Ok(Self {
-            worker,
+            workers: Arc::new(workers),
             schema_id,
+            plan_cache: None,
+            persisted_queries: None,
+            persisted_query_validation: None,
+            shut_down: false,
             t: PhantomData,
         })
     }
 
+    /// Enable an in-process cache of up to `capacity` successful query plans.
+    ///
+    /// The cache is keyed on the schema and the normalized planning request,
+    /// and is shared with the planners this one is updated to.
+    /// The plans of a schema are evicted from the cache when its planner is successfully
+    /// [updated](Planner::update), or dropped.
+    pub fn with_plan_cache(mut self, capacity: NonZeroUsize) -> Self {
+        self.plan_cache = Some(Arc::new(PlanCache::new(capacity)));
+        self
+    }
+
+    /// Query plan cache statistics, if the cache is enabled
+    pub fn plan_cache_stats(&self) -> Option<PlanCacheStats> {
+        self.plan_cache.as_ref().map(|cache| cache.stats())
+    }
+
     /// Update `Planner` from a schema string
+    ///
+    /// The update is atomic across the pool of workers:
+    /// either every worker accepted the new schema, or the existing schema is kept in place.
+    /// Once the new schema is accepted, the plans cached for the existing schema are evicted.
     pub async fn update(
         &self,
         schema: String,
@@ -486,44 +618,129 @@ where
     ) -> Result<Self, Vec<PlannerError>> {
         let schema_id: u64 = rand::random();
 
-        let worker_is_set_up = self
-            .worker
-            .request::<PlanCmd, BridgeSetupResult<serde_json::Value>>(PlanCmd::UpdateSchema {
-                schema,
-                config,
-                schema_id,
-            })
-            .await
-            .map_err(|e| {
-                vec![WorkerError {
-                    name: Some("planner setup error".to_string()),
-                    message: Some(e.to_string()),
-                    stack: None,
-                    extensions: None,
-                    locations: Default::default(),
-                }
-                .into()]
-            });
+        // If the update failed, we keep the existing schema in place,
+        // and remove the new one from the workers that might have accepted it
+        if let Err(setup_error) =
+            Self::set_up_schema(&self.workers, schema, config, schema_id).await
+        {
+            self.workers.remove_restart_command(&schema_id.to_string());
+            self.workers.notify(PlanCmd::Exit { schema_id }).await;
+            return Err(setup_error);
+        }
 
-        // If the update failed, we keep the existing schema in place
-        match worker_is_set_up {
-            Err(setup_error) => {
-                return Err(setup_error);
-            }
-            Ok(setup) => {
-                if let Some(error) = setup.errors {
-                    return Err(error);
+        // The persisted queries are validated against the new schema, but don't prevent the update
+        let persisted_query_validation = match &self.persisted_queries {
+            Some(manifest) => {
+                match Self::validate_manifest(&self.workers, schema_id, manifest).await {
+                    Ok(validation) => Some(validation),
+                    Err(e) => {
+                        self.workers.remove_restart_command(&schema_id.to_string());
+                        self.workers.notify(PlanCmd::Exit { schema_id }).await;
+                        return Err(setup_error(e));
+                    }
                 }
             }
+            None => None,
+        };
+
+        if let Some(cache) = &self.plan_cache {
+            cache.invalidate(self.schema_id);
         }
 
         Ok(Self {
-            worker: self.worker.clone(),
+            workers: self.workers.clone(),
             schema_id,
+            plan_cache: self.plan_cache.clone(),
+            persisted_queries: self.persisted_queries.clone(),
+            persisted_query_validation,
+            shut_down: false,
             t: PhantomData,
         })
     }
 
+    /// Load a manifest of persisted queries, and validate each of its operations against the schema
+    ///
+    /// The manifest is shared with the planners this one is updated to, and validated against their schemas.
+    /// Operations that don't validate are reported by [`Planner::persisted_query_validation`],
+    /// and can still be planned, with errors.
+    pub async fn with_persisted_queries(
+        mut self,
+        manifest: PersistedQueryManifest,
+    ) -> Result<Self, crate::error::Error> {
+        let validation = Self::validate_manifest(&self.workers, self.schema_id, &manifest).await?;
+        self.persisted_queries = Some(Arc::new(manifest));
+        self.persisted_query_validation = Some(validation);
+        Ok(self)
+    }
+
+    /// The operations of the persisted query manifest that don't validate against the schema,
+    /// if a manifest was loaded
+    pub fn persisted_query_validation(&self) -> Option<&ManifestValidation> {
+        self.persisted_query_validation.as_ref()
+    }
+
+    async fn validate_manifest(
+        workers: &JsWorkerPool,
+        schema_id: u64,
+        manifest: &PersistedQueryManifest,
+    ) -> Result<ManifestValidation, crate::error::Error> {
+        let (ids, queries): (Vec<_>, Vec<_>) = manifest
+            .iter()
+            .map(|(id, body)| (id.to_string(), body.to_string()))
+            .unzip();
+        let results: Vec<PlanResult<ValidatedOperation>> = workers
+            .request(PlanCmd::ValidateBatch { queries, schema_id })
+            .await?;
+        if results.len() != ids.len() {
+            return Err(crate::error::Error::DenoRuntime(format!(
+                "validate_manifest: expected {} results, got {}",
+                ids.len(),
+                results.len()
+            )));
+        }
+
+        Ok(ManifestValidation {
+            invalid: ids
+                .into_iter()
+                .zip(results)
+                .filter_map(|(id, result)| result.into_result().err().map(|errors| (id, errors)))
+                .collect(),
+        })
+    }
+
+    /// Load a schema in every worker of the pool, under `schema_id`.
+    async fn set_up_schema(
+        workers: &JsWorkerPool,
+        schema: String,
+        config: QueryPlannerConfig,
+        schema_id: u64,
+    ) -> Result<(), Vec<PlannerError>> {
+        let command = PlanCmd::UpdateSchema {
+            schema,
+            config,
+            schema_id,
+        };
+        let responses = workers
+            .broadcast::<PlanCmd, BridgeSetupResult<serde_json::Value>>(command.clone())
+            .await;
+
+        for response in responses {
+            match response {
+                Err(e) => return Err(setup_error(e)),
+                Ok(setup) => {
+                    if let Some(error) = setup.errors {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        // A worker that crashes loses its schemas, so they are loaded again when it is respawned.
+        workers
+            .set_restart_command(schema_id.to_string(), &command)
+            .map_err(setup_error)
+    }
+
     /// Plan a query against an instantiated query planner
     pub async fn plan(
         &self,
@@ -531,19 +748,273 @@ where
         operation_name: Option<String>,
         options: PlanOptions,
     ) -> Result<PlanResult<T>, crate::error::Error> {
-        self.worker
-            .request(PlanCmd::Plan {
+        self.plan_inner(query, operation_name, options, None).await
+    }
+
+    /// Plan an operation of the persisted query manifest, by its id
+    ///
+    /// Fails with [`Error::UnknownPersistedQuery`](crate::error::Error::UnknownPersistedQuery)
+    /// if no manifest was loaded, or if it doesn't have an operation with this id.
+    pub async fn plan_persisted_query(
+        &self,
+        id: &str,
+        operation_name: Option<String>,
+        options: PlanOptions,
+    ) -> Result<PlanResult<T>, crate::error::Error> {
+        let query = self
+            .persisted_queries
+            .as_ref()
+            .and_then(|manifest| manifest.get(id))
+            .ok_or_else(|| crate::error::Error::UnknownPersistedQuery { id: id.to_string() })?;
+        self.plan(query.to_string(), operation_name, options).await
+    }
+
+    /// Plan a query against an instantiated query planner, for at most `timeout`
+    ///
+    /// If planning takes longer than `timeout`, the JavaScript worker is interrupted,
+    /// and [`Error::Timeout`](crate::error::Error::Timeout) is returned.
+    /// The planner remains usable afterwards.
+    pub async fn plan_with_timeout(
+        &self,
+        query: String,
+        operation_name: Option<String>,
+        options: PlanOptions,
+        timeout: Duration,
+    ) -> Result<PlanResult<T>, crate::error::Error> {
+        self.plan_inner(query, operation_name, options, Some(timeout))
+            .await
+    }
+
+    async fn plan_inner(
+        &self,
+        query: String,
+        operation_name: Option<String>,
+        options: PlanOptions,
+        timeout: Option<Duration>,
+    ) -> Result<PlanResult<T>, crate::error::Error> {
+        let cache = match &self.plan_cache {
+            Some(cache) => cache,
+            None => {
+                return self
+                    .request_plan(query, operation_name, options, timeout)
+                    .await
+            }
+        };
+
+        let key = PlanCacheKey::new(self.schema_id, &query, operation_name.as_deref(), &options);
+        let payload = match cache.get(&key) {
+            Some(payload) => payload,
+            None => {
+                let payload: serde_json::Value = self
+                    .request_plan(query, operation_name, options, timeout)
+                    .await?;
+                cache_plan(cache, key, &payload);
+                payload
+            }
+        };
+
+        deserialize_plan(payload)
+    }
+
+    /// Plan a batch of queries against an instantiated query planner, in a single round trip
+    ///
+    /// The results are in the same order as `operations`. An operation that can't be planned gets
+    /// a [`PlanResult`] with errors, and doesn't fail the rest of the batch.
+    pub async fn plan_batch(
+        &self,
+        operations: Vec<(String, Option<String>, PlanOptions)>,
+    ) -> Result<Vec<PlanResult<T>>, crate::error::Error> {
+        let mut payloads: Vec<Option<serde_json::Value>> = vec![None; operations.len()];
+        // The position and cache key of the operations we need to send to the query planner
+        let mut misses = Vec::new();
+        let mut batch = Vec::new();
+
+        for (index, (query, operation_name, options)) in operations.into_iter().enumerate() {
+            let key = self.plan_cache.as_ref().map(|_| {
+                PlanCacheKey::new(self.schema_id, &query, operation_name.as_deref(), &options)
+            });
+            if let (Some(cache), Some(key)) = (&self.plan_cache, &key) {
+                if let Some(payload) = cache.get(key) {
+                    payloads[index] = Some(payload);
+                    continue;
+                }
+            }
+
+            misses.push((index, key));
+            batch.push(BatchOperation {
+                query,
+                operation_name,
+                options,
+            });
+        }
+
+        if !batch.is_empty() {
+            let results: Vec<serde_json::Value> = self
+                .workers
+                .request(PlanCmd::PlanBatch {
+                    operations: batch,
+                    schema_id: self.schema_id,
+                })
+                .await?;
+            if results.len() != misses.len() {
+                return Err(crate::error::Error::DenoRuntime(format!(
+                    "plan_batch: expected {} plans, got {}",
+                    misses.len(),
+                    results.len()
+                )));
+            }
+
+            for ((index, key), payload) in misses.into_iter().zip(results) {
+                if let (Some(cache), Some(key)) = (&self.plan_cache, key) {
+                    cache_plan(cache, key, &payload);
+                }
+                payloads[index] = Some(payload);
+            }
+        }
+
+        payloads
+            .into_iter()
+            .map(|payload| deserialize_plan(payload.expect("every operation was planned")))
+            .collect()
+    }
+
+    async fn request_plan<Response>(
+        &self,
+        query: String,
+        operation_name: Option<String>,
+        options: PlanOptions,
+        timeout: Option<Duration>,
+    ) -> Result<Response, crate::error::Error>
+    where
+        Response: DeserializeOwned + Send + Debug + 'static,
+    {
+        let command = PlanCmd::Plan {
+            query,
+            operation_name,
+            schema_id: self.schema_id,
+            options,
+        };
+        match timeout {
+            Some(timeout) => self.workers.request_with_timeout(command, timeout).await,
+            None => self.workers.request(command).await,
+        }
+    }
+
+    /// Validate a query against the API schema, without planning it
+    ///
+    /// The query goes through the same parsing and validation as in [`Planner::plan`],
+    /// and fails with the same errors, but no query plan is built.
+    pub async fn validate(
+        &self,
+        query: String,
+        operation_name: Option<String>,
+    ) -> Result<PlanResult<ValidatedOperation>, crate::error::Error> {
+        self.workers
+            .request(PlanCmd::Validate {
+                query,
+                operation_name,
+                schema_id: self.schema_id,
+            })
+            .await
+    }
+
+    /// Normalize an operation into an executable document, and hash it
+    ///
+    /// The normalized document only holds the selected operation, with fragments inlined,
+    /// and selections, arguments and variable definitions in a stable order. Unlike the
+    /// [operation signature](Planner::operation_signature), literals are kept.
+    /// Its hash can key caches and persisted query manifests consistently across routers.
+    /// An operation that doesn't validate gets a [`PlanResult`] with errors.
+    pub async fn normalized_operation(
+        &self,
+        query: String,
+        operation_name: Option<String>,
+    ) -> Result<PlanResult<NormalizedOperation>, crate::error::Error> {
+        let result: PlanResult<NormalizedDocument> = self
+            .workers
+            .request(PlanCmd::NormalizedOperation {
+                query,
+                operation_name,
+                schema_id: self.schema_id,
+            })
+            .await?;
+
+        Ok(PlanResult {
+            data: result
+                .data
+                .map(|normalized| NormalizedOperation::new(normalized.document)),
+            usage_reporting: result.usage_reporting,
+            errors: result.errors,
+            statistics: result.statistics,
+        })
+    }
+
+    /// Coerce the variables of an operation against the API schema, with the GraphQL input coercion rules
+    ///
+    /// Default values are applied, and every invalid value is reported with its path in `variables`.
+    /// An operation that doesn't validate gets a [`PlanResult`] with errors.
+    pub async fn coerce_variables(
+        &self,
+        query: String,
+        operation_name: Option<String>,
+        variables: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<PlanResult<CoercedVariables>, crate::error::Error> {
+        self.workers
+            .request(PlanCmd::CoerceVariables {
                 query,
                 operation_name,
+                variables: Variables(variables),
                 schema_id: self.schema_id,
+            })
+            .await
+    }
+
+    /// Estimate the cost of an operation from the `@cost` and `@listSize` directives of the schema
+    ///
+    /// `variables` are used to read the slicing arguments of lists, and the arguments that have a cost.
+    /// An operation that doesn't validate gets a [`PlanResult`] with errors.
+    /// So does any operation if the supergraph doesn't link the cost spec,
+    /// with a single `COST_SPEC_NOT_LINKED` error.
+    pub async fn demand_cost(
+        &self,
+        query: String,
+        operation_name: Option<String>,
+        variables: serde_json::Map<String, serde_json::Value>,
+        options: DemandCostOptions,
+    ) -> Result<PlanResult<DemandCost>, crate::error::Error> {
+        self.workers
+            .request(PlanCmd::DemandCost {
+                query,
+                operation_name,
+                variables: Variables(variables),
                 options,
+                schema_id: self.schema_id,
+            })
+            .await
+    }
+
+    /// Collect the `@authenticated`, `@requiresScopes` and `@policy` requirements an operation
+    /// touches, with the schema coordinates of the types and fields they are applied to
+    ///
+    /// The operation is validated, but not planned.
+    /// An operation that doesn't validate gets a [`PlanResult`] with errors.
+    pub async fn authorization_requirements(
+        &self,
+        query: String,
+        operation_name: Option<String>,
+    ) -> Result<PlanResult<AuthorizationRequirements>, crate::error::Error> {
+        self.workers
+            .request(PlanCmd::AuthorizationRequirements {
+                query,
+                operation_name,
+                schema_id: self.schema_id,
             })
             .await
     }
 
     /// Generate the API schema from the current schema
     pub async fn api_schema(&self) -> Result<ApiSchema, crate::error::Error> {
-        self.worker
+        self.workers
             .request(PlanCmd::ApiSchema {
                 schema_id: self.schema_id,
             })
@@ -555,7 +1026,7 @@ where
         &self,
         query: String,
     ) -> Result<IntrospectionResponse, crate::error::Error> {
-        self.worker
+        self.workers
             .request(PlanCmd::Introspect {
                 query,
                 schema_id: self.schema_id,
@@ -569,7 +1040,7 @@ where
         query: String,
         operation_name: Option<String>,
     ) -> Result<String, crate::error::Error> {
-        self.worker
+        self.workers
             .request(PlanCmd::Signature {
                 query,
                 operation_name,
@@ -580,12 +1051,115 @@ where
 
     /// Extract the subgraph schemas from the supergraph schema
     pub async fn subgraphs(&self) -> Result<HashMap<String, String>, crate::error::Error> {
-        self.worker
+        self.workers
             .request(PlanCmd::Subgraphs {
                 schema_id: self.schema_id,
             })
             .await
     }
+
+    /// Statistics about the V8 heap of each JavaScript worker backing this planner
+    ///
+    /// The workers are shared with the planners this one was updated from or to,
+    /// so the statistics cover the memory used by all of their schemas.
+    pub async fn heap_statistics(&self) -> Result<Vec<HeapStatistics>, crate::error::Error> {
+        self.workers.heap_statistics().await
+    }
+
+    /// A snapshot of the request queue of each JavaScript worker backing this planner
+    ///
+    /// Like [`Planner::heap_statistics`], it covers the planners sharing the same workers.
+    pub async fn queue_statistics(&self) -> Vec<QueueStatistics> {
+        self.workers.queue_statistics().await
+    }
+
+    /// Remove the schema of this planner from its JavaScript workers, and shut them down
+    /// if no other planner uses them.
+    ///
+    /// The workers process the requests that were already sent to them before exiting,
+    /// and we wait for them to do so for at most `timeout`.
+    /// Unlike dropping the planner, this doesn't leave the workers to exit in the background.
+    pub async fn shutdown(mut self, timeout: Duration) -> ShutdownOutcome {
+        self.remove_schema();
+        self.shut_down = true;
+        let workers = self.workers.clone();
+        let schema_id = self.schema_id;
+        drop(self);
+
+        workers.notify(PlanCmd::Exit { schema_id }).await;
+        match Arc::try_unwrap(workers) {
+            Ok(workers) => {
+                if workers.shutdown(timeout).await {
+                    ShutdownOutcome::Exited
+                } else {
+                    ShutdownOutcome::Unclean
+                }
+            }
+            Err(_) => ShutdownOutcome::StillInUse,
+        }
+    }
+
+    /// Forget about the schema of this planner, before letting the workers know
+    fn remove_schema(&self) {
+        if let Some(cache) = &self.plan_cache {
+            cache.invalidate(self.schema_id);
+        }
+
+        self.workers
+            .remove_restart_command(&self.schema_id.to_string());
+    }
+}
+
+/// What happened to the JavaScript workers of a planner, once it was [shut down](Planner::shutdown).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownOutcome {
+    /// The workers processed the requests sent to them, and their isolates exited cleanly.
+    Exited,
+    /// The workers are shared with the planners this one was updated from or to,
+    /// so only the schema of this planner was removed from them.
+    StillInUse,
+    /// Some of the workers crashed, or didn't exit in time.
+    /// They are left to exit in the background.
+    Unclean,
+}
+
+fn setup_error(e: crate::error::Error) -> Vec<PlannerError> {
+    vec![WorkerError {
+        name: Some("planner setup error".to_string()),
+        message: Some(e.to_string()),
+        stack: None,
+        extensions: None,
+        locations: Default::default(),
+    }
+    .into()]
+}
+
+// Errors carry locations in the original query text, so we only cache plans
+fn is_cacheable(payload: &serde_json::Value) -> bool {
+    payload.get("data").map_or(false, |data| !data.is_null())
+}
+
+// The planning statistics describe the request that computed the plan, so they are left out
+// of the cached payload, rather than being reported again on every hit.
+fn cache_plan(cache: &PlanCache, key: PlanCacheKey, payload: &serde_json::Value) {
+    if !is_cacheable(payload) {
+        return;
+    }
+    let mut cached = payload.clone();
+    if let Some(fields) = cached.as_object_mut() {
+        fields.remove("statistics");
+    }
+    cache.insert(key, cached);
+}
+
+fn deserialize_plan<T>(payload: serde_json::Value) -> Result<PlanResult<T>, crate::error::Error>
+where
+    T: DeserializeOwned + Send + Debug + 'static,
+{
+    serde_json::from_value(payload).map_err(|e| crate::error::Error::ParameterDeserialization {
+        message: format!("deno: couldn't deserialize response : `{e:?}`"),
+        id: "plan".to_string(),
+    })
 }
 
 impl<T> Drop for Planner<T>
@@ -593,19 +1167,16 @@ where
     T: DeserializeOwned + Send + Debug + 'static,
 {
     fn drop(&mut self) {
-        // Send a PlanCmd::Exit signal
-        let worker_clone = self.worker.clone();
-        let schema_id = self.schema_id;
-        let _ = std::thread::spawn(move || {
-            let runtime = tokio::runtime::Builder::new_current_thread()
-                .build()
-                .unwrap();
+        if self.shut_down {
+            return;
+        }
+        self.remove_schema();
 
-            let _ = runtime.block_on(async move {
-                worker_clone.send(None, PlanCmd::Exit { schema_id }).await
-            });
-        })
-        .join();
+        // Send a PlanCmd::Exit signal to every worker, without waiting for room in their queues
+        // so dropping doesn't block. `Planner::shutdown` is the way to wait for the workers to exit.
+        self.workers.try_notify(PlanCmd::Exit {
+            schema_id: self.schema_id,
+        });
     }
 }
 
@@ -617,7 +1188,7 @@ pub struct PlanOptions {
     pub override_conditions: Vec<String>,
 }
 
-#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
 #[serde(tag = "kind")]
 enum PlanCmd {
     #[serde(rename_all = "camelCase")]
@@ -634,23 +1205,82 @@ enum PlanCmd {
         options: PlanOptions,
     },
     #[serde(rename_all = "camelCase")]
-    ApiSchema { schema_id: u64 },
+    PlanBatch {
+        operations: Vec<BatchOperation>,
+        schema_id: u64,
+    },
     #[serde(rename_all = "camelCase")]
-    Introspect { query: String, schema_id: u64 },
+    Validate {
+        query: String,
+        operation_name: Option<String>,
+        schema_id: u64,
+    },
     #[serde(rename_all = "camelCase")]
-    Signature {
+    NormalizedOperation {
         query: String,
         operation_name: Option<String>,
         schema_id: u64,
     },
     #[serde(rename_all = "camelCase")]
-    Subgraphs { schema_id: u64 },
+    CoerceVariables {
+        query: String,
+        operation_name: Option<String>,
+        variables: Variables,
+        schema_id: u64,
+    },
     #[serde(rename_all = "camelCase")]
-    Exit { schema_id: u64 },
+    ValidateBatch {
+        queries: Vec<String>,
+        schema_id: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    DemandCost {
+        query: String,
+        operation_name: Option<String>,
+        variables: Variables,
+        options: DemandCostOptions,
+        schema_id: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    AuthorizationRequirements {
+        query: String,
+        operation_name: Option<String>,
+        schema_id: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    ApiSchema { schema_id: u64 },
+    #[serde(rename_all = "camelCase")]
+    Introspect { query: String, schema_id: u64 },
+    #[serde(rename_all = "camelCase")]
+    Signature {
+        query: String,
+        operation_name: Option<String>,
+        schema_id: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Subgraphs { schema_id: u64 },
+    #[serde(rename_all = "camelCase")]
+    Exit { schema_id: u64 },
+}
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct BatchOperation {
+    query: String,
+    operation_name: Option<String>,
+    options: PlanOptions,
 }
+
+/// The variables of an operation, as sent to the workers.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+struct Variables(serde_json::Map<String, serde_json::Value>);
+
 #[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 /// Query planner configuration
+///
+/// More options may be added, so start from [`QueryPlannerConfig::default()`] and set the ones you need.
 pub struct QueryPlannerConfig {
     //exposeDocumentNodeInFetchNode?: boolean;
 
@@ -695,6 +1325,17 @@ pub struct QueryPlannerConfig {
     /// If you aren't aware of this flag, you probably don't need it.
     /// Defaults to false.
     pub type_conditioned_fetching: bool,
+
+    /// Limits on the shape of operations, checked before they are validated and planned.
+    ///
+    /// Operations that exceed them are rejected with a `PlanError`.
+    pub operation_limits: OperationLimits,
+
+    /// Whether [`Planner::plan`] reports how long each phase of planning took,
+    /// and how many plans were evaluated, in [`PlanResult::statistics`].
+    ///
+    /// Useful to tell why a plan is slow. Defaults to false.
+    pub planning_statistics: bool,
 }
 
 impl Default for QueryPlannerConfig {
@@ -708,10 +1349,68 @@ impl Default for QueryPlannerConfig {
             generate_query_fragments: None,
             debug: Default::default(),
             type_conditioned_fetching: false,
+            operation_limits: Default::default(),
+            planning_statistics: false,
+        }
+    }
+}
+
+/// How the JavaScript workers backing a [`Planner`] are set up.
+///
+/// The workers are created along with the planner, and shared with the planners it is updated to,
+/// so these options can't be changed by [`Planner::update`].
+/// New options may be added, so start from [`PlannerOptions::default`] and set the ones you need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlannerOptions {
+    /// The number of workers the schema is loaded in, and planning requests are spread across.
+    ///
+    /// Defaults to 1.
+    pub pool_size: NonZeroUsize,
+    /// How the requests waiting for each worker are queued.
+    pub queue_policy: QueuePolicy,
+    /// How the heap of each worker grows.
+    ///
+    /// Defaults to [`default_heap_policy`](crate::heap::default_heap_policy).
+    pub heap_policy: HeapPolicy,
+}
+
+impl Default for PlannerOptions {
+    fn default() -> Self {
+        Self {
+            pool_size: NonZeroUsize::MIN,
+            queue_policy: Default::default(),
+            heap_policy: crate::heap::default_heap_policy(),
         }
     }
 }
 
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+/// Limits on the shape of the operations sent to the query planner.
+///
+/// Fragment spreads are expanded when the depth, aliases and root fields are counted.
+/// Every limit defaults to `None`, meaning no limit.
+pub struct OperationLimits {
+    /// The maximum nesting of fields, root fields being at depth 1.
+    ///
+    /// Exceeding it is reported with the `MAX_DEPTH_LIMIT` error code.
+    pub max_depth: Option<u32>,
+    /// The maximum number of aliased fields.
+    ///
+    /// Exceeding it is reported with the `MAX_ALIASES_LIMIT` error code.
+    pub max_aliases: Option<u32>,
+    /// The maximum number of fields in the root selection set.
+    ///
+    /// Exceeding it is reported with the `MAX_ROOT_FIELDS_LIMIT` error code.
+    pub max_root_fields: Option<u32>,
+    /// The maximum number of lexical tokens in the operation document. This one is checked
+    /// before the document is parsed.
+    ///
+    /// Exceeding it is reported with the `MAX_TOKENS_LIMIT` error code.
+    pub max_tokens: Option<u32>,
+}
+
 #[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 /// Option for `@defer` directive support
@@ -773,6 +1472,10 @@ mod tests {
     use std::collections::BTreeMap;
 
     use super::*;
+    use crate::authorization::Requirement;
+    use crate::demand_control::FieldCost;
+    use crate::queue::QueueFullAction;
+    use crate::variables::VariableError;
 
     const QUERY: &str = include_str!("testdata/query.graphql");
     const QUERY2: &str = include_str!("testdata/query2.graphql");
@@ -800,6 +1503,8 @@ mod tests {
     const UNSUPPORTED_FEATURE_FOR_SECURITY: &str =
         include_str!("testdata/unsupported_feature_for_security.graphql");
     const PROGRESSIVE_OVERRIDE: &str = include_str!("testdata/progressive_override.graphql");
+    const DEMAND_CONTROL: &str = include_str!("testdata/demand_control.graphql");
+    const AUTHORIZATION: &str = include_str!("testdata/authorization.graphql");
 
     #[tokio::test]
     async fn anonymous_query_works() {
@@ -1236,6 +1941,173 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn operation_limits() {
+        let planner = Planner::<serde_json::Value>::new(
+            SCHEMA.to_string(),
+            QueryPlannerConfig {
+                operation_limits: OperationLimits {
+                    max_depth: Some(2),
+                    max_aliases: Some(1),
+                    max_root_fields: Some(2),
+                    max_tokens: Some(30),
+                },
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        async fn error_codes(planner: &Planner<serde_json::Value>, query: &str) -> Vec<String> {
+            let errors = planner
+                .plan(query.to_string(), None, PlanOptions::default())
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap_err();
+            assert_eq!(
+                "## GraphQLValidationFailure\n",
+                errors.usage_reporting.stats_report_key
+            );
+            errors
+                .errors
+                .iter()
+                .map(|error| error.extensions.as_ref().unwrap().code.clone())
+                .collect()
+        }
+
+        assert!(planner
+            .plan("{ a: me { id } }".to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .is_ok());
+        assert_eq!(
+            vec!["MAX_DEPTH_LIMIT"],
+            error_codes(&planner, "{ me { name { first } } }").await
+        );
+        assert_eq!(
+            vec!["MAX_ALIASES_LIMIT"],
+            error_codes(&planner, "{ a: me { id } b: me { id } }").await
+        );
+        // fragments are expanded
+        assert_eq!(
+            vec!["MAX_DEPTH_LIMIT", "MAX_ROOT_FIELDS_LIMIT"],
+            error_codes(
+                &planner,
+                "query { ...F } fragment F on Query { me { reviews { id } } me { id } me { id } }"
+            )
+            .await
+        );
+        // the token limit is checked first
+        assert_eq!(
+            vec!["MAX_TOKENS_LIMIT"],
+            error_codes(
+                &planner,
+                "{ me { id } me { id } me { id } me { id } me { id } me { id } me { id } me { id } }"
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn planning_statistics() {
+        let planner = Planner::<serde_json::Value>::new(
+            SCHEMA.to_string(),
+            QueryPlannerConfig {
+                planning_statistics: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let payload = planner
+            .plan(
+                "{ me { id reviews { body } } }".to_string(),
+                None,
+                PlanOptions::default(),
+            )
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let statistics = payload.statistics.unwrap();
+        let phases = statistics.phases;
+        assert!(phases.parsing.is_some());
+        assert!(phases.validation.is_some());
+        assert!(phases.operation_from_document.is_some());
+        assert!(phases.usage_reporting.is_some());
+        assert!(phases.query_planning.is_some());
+        assert!(phases.formatting.is_some());
+        assert!(statistics.evaluated_plan_count.unwrap() >= 1);
+
+        // planning stops at validation
+        let payload = planner
+            .plan(
+                "{ me { unknown } }".to_string(),
+                None,
+                PlanOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert!(payload.errors.is_some());
+        let statistics = payload.statistics.unwrap();
+        assert!(statistics.phases.validation.is_some());
+        assert_eq!(None, statistics.phases.operation_from_document);
+        assert_eq!(None, statistics.evaluated_plan_count);
+
+        // statistics are only reported if enabled
+        let planner = Planner::<serde_json::Value>::new(SCHEMA.to_string(), Default::default())
+            .await
+            .unwrap();
+        let payload = planner
+            .plan("{ me { id } }".to_string(), None, PlanOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(None, payload.statistics);
+    }
+
+    #[tokio::test]
+    async fn cached_plans_have_no_planning_statistics() {
+        let planner = Planner::<serde_json::Value>::new(
+            SCHEMA.to_string(),
+            QueryPlannerConfig {
+                planning_statistics: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .with_plan_cache(NonZeroUsize::new(10).unwrap());
+
+        let planned = planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert!(planned.statistics.is_some());
+
+        // the second plan comes from the cache, and wasn't timed
+        let cached = planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(planned.data, cached.data);
+        assert_eq!(None, cached.statistics);
+        assert_eq!(1, planner.plan_cache_stats().unwrap().hits);
+
+        // the same goes for batches
+        let batch = planner
+            .plan_batch(vec![(QUERY.to_string(), None, PlanOptions::default())])
+            .await
+            .unwrap();
+        assert_eq!(None, batch[0].statistics);
+    }
+
     #[tokio::test]
     // A series of queries that should fail graphql-js's validate function.  The federation
     // query planning logic automatically does some validation in order to do its duties.
@@ -1427,74 +2299,734 @@ mod tests {
             "query ExampleQuery { thisDoesntExist }".to_string(),
             None,
         )
-        .await;
+        .await;
+    }
+
+    async fn assert_errors(
+        expected_errors: Vec<PlanError>,
+        query: String,
+        operation_name: Option<String>,
+    ) {
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
+
+        let actual = planner
+            .plan(query, operation_name, PlanOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(expected_errors, actual.errors.unwrap());
+    }
+
+    #[tokio::test]
+    async fn it_doesnt_race() {
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
+
+        let query_1_response = planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .data
+            .unwrap();
+
+        let query_2_response = planner
+            .plan(QUERY2.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .data
+            .unwrap();
+
+        let all_futures = stream::iter((0..1000).map(|i| {
+            let (query, fut) = if i % 2 == 0 {
+                (
+                    QUERY,
+                    planner.plan(QUERY.to_string(), None, PlanOptions::default()),
+                )
+            } else {
+                (
+                    QUERY2,
+                    planner.plan(QUERY2.to_string(), None, PlanOptions::default()),
+                )
+            };
+
+            async move { (query, fut.await.unwrap()) }
+        }));
+
+        all_futures
+            .for_each_concurrent(None, |fut| async {
+                let (query, plan_result) = fut.await;
+                if query == QUERY {
+                    assert_eq!(query_1_response, plan_result.data.unwrap());
+                } else {
+                    assert_eq!(query_2_response, plan_result.data.unwrap());
+                }
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn identical_requests_are_planned_once_across_the_pool() {
+        let planner = Planner::<serde_json::Value>::new_with_options(
+            SCHEMA.to_string(),
+            QueryPlannerConfig::default(),
+            PlannerOptions {
+                pool_size: NonZeroUsize::new(4).unwrap(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let plans = futures::future::join_all(
+            (0..8).map(|_| planner.plan(QUERY.to_string(), None, PlanOptions::default())),
+        )
+        .await;
+        for plan in plans {
+            plan.unwrap().into_result().unwrap();
+        }
+
+        // the workers log every response they send
+        logs_assert(|lines: &[&str]| {
+            let planned = lines
+                .iter()
+                .filter(|line| {
+                    line.contains("plan_worker: sending payload")
+                        && line.contains("formattedQueryPlan")
+                })
+                .count();
+            assert_eq!(1, planned, "{}", lines.join("\n"));
+            Ok(())
+        });
+    }
+
+    #[tokio::test]
+    async fn pool_doesnt_race() {
+        let planner = Planner::<serde_json::Value>::new_with_options(
+            SCHEMA.to_string(),
+            QueryPlannerConfig::default(),
+            PlannerOptions {
+                pool_size: NonZeroUsize::new(4).unwrap(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let query_1_response = planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .data
+            .unwrap();
+
+        let query_2_response = planner
+            .plan(QUERY2.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .data
+            .unwrap();
+
+        let all_futures = stream::iter((0..1000).map(|i| {
+            let (query, fut) = if i % 2 == 0 {
+                (
+                    QUERY,
+                    planner.plan(QUERY.to_string(), None, PlanOptions::default()),
+                )
+            } else {
+                (
+                    QUERY2,
+                    planner.plan(QUERY2.to_string(), None, PlanOptions::default()),
+                )
+            };
+
+            async move { (query, fut.await.unwrap()) }
+        }));
+
+        all_futures
+            .for_each_concurrent(None, |fut| async {
+                let (query, plan_result) = fut.await;
+                if query == QUERY {
+                    assert_eq!(query_1_response, plan_result.data.unwrap());
+                } else {
+                    assert_eq!(query_2_response, plan_result.data.unwrap());
+                }
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn pool_update_is_atomic() {
+        let query = "{ me { id name {first } reviews { id author { name { first } } body } } }";
+        let planner = Planner::<serde_json::Value>::new_with_options(
+            SCHEMA_WITHOUT_REVIEW_BODY.to_string(),
+            QueryPlannerConfig::default(),
+            PlannerOptions {
+                pool_size: NonZeroUsize::new(3).unwrap(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        // an invalid schema is rejected, and the existing one is kept in place
+        planner
+            .update(
+                "this is not a schema".to_string(),
+                QueryPlannerConfig::default(),
+            )
+            .await
+            .unwrap_err();
+
+        let updated_planner = planner
+            .update(SCHEMA.to_string(), QueryPlannerConfig::default())
+            .await
+            .unwrap();
+
+        // every worker of the pool plans against the schema of its planner
+        for _ in 0..6 {
+            planner
+                .plan(query.to_string(), None, PlanOptions::default())
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap_err();
+            updated_planner
+                .plan(query.to_string(), None, PlanOptions::default())
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn plan_cache() {
+        let query = "{ me { id name {first } reviews { id author { name { first } } body } } }";
+        let planner = Planner::<serde_json::Value>::new(
+            SCHEMA_WITHOUT_REVIEW_BODY.to_string(),
+            QueryPlannerConfig::default(),
+        )
+        .await
+        .unwrap()
+        .with_plan_cache(NonZeroUsize::new(10).unwrap());
+
+        let first = planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        // same query, different ignored tokens
+        let second = planner
+            .plan(
+                format!("# a comment\n{}", QUERY.replace(' ', "  ")),
+                None,
+                PlanOptions::default(),
+            )
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(first.data, second.data);
+        assert_eq!(first.usage_reporting, second.usage_reporting);
+
+        // errors are not cached
+        for _ in 0..2 {
+            planner
+                .plan(query.to_string(), None, PlanOptions::default())
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap_err();
+        }
+
+        assert_eq!(
+            Some(PlanCacheStats {
+                hits: 1,
+                misses: 3,
+                len: 1,
+                capacity: 10,
+            }),
+            planner.plan_cache_stats()
+        );
+
+        // the plans of the previous schema are evicted once the update succeeds
+        let updated_planner = planner
+            .update(SCHEMA.to_string(), QueryPlannerConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(0, updated_planner.plan_cache_stats().unwrap().len);
+
+        updated_planner
+            .plan(query.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(1, updated_planner.plan_cache_stats().unwrap().len);
+
+        drop(planner);
+        assert_eq!(1, updated_planner.plan_cache_stats().unwrap().len);
+    }
+
+    #[tokio::test]
+    async fn heap_statistics() {
+        let planner = Planner::<serde_json::Value>::new_with_options(
+            SCHEMA.to_string(),
+            QueryPlannerConfig::default(),
+            PlannerOptions {
+                pool_size: NonZeroUsize::new(2).unwrap(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap();
+
+        let statistics = planner.heap_statistics().await.unwrap();
+        assert_eq!(2, statistics.len());
+        for worker_statistics in statistics {
+            assert!(worker_statistics.used_heap_size > 0);
+            assert!(worker_statistics.used_heap_size <= worker_statistics.total_heap_size);
+            assert!(worker_statistics.total_heap_size <= worker_statistics.heap_size_limit);
+        }
+    }
+
+    #[tokio::test]
+    async fn heap_policy_that_cant_grow_is_rejected() {
+        let errors = Planner::<serde_json::Value>::new_with_options(
+            SCHEMA.to_string(),
+            QueryPlannerConfig::default(),
+            PlannerOptions {
+                heap_policy: HeapPolicy {
+                    growth_factor: 1.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(1, errors.len());
+    }
+
+    #[tokio::test]
+    async fn queue_statistics() {
+        let planner = Planner::<serde_json::Value>::new_with_options(
+            SCHEMA.to_string(),
+            QueryPlannerConfig::default(),
+            PlannerOptions {
+                pool_size: NonZeroUsize::new(2).unwrap(),
+                queue_policy: QueuePolicy {
+                    capacity: 4,
+                    when_full: QueueFullAction::Reject,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap();
+
+        // Every request was answered, so nothing is left in the queues
+        assert_eq!(
+            vec![QueueStatistics::default(); 2],
+            planner.queue_statistics().await
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
+        let updated_planner = planner
+            .update(SCHEMA.to_string(), QueryPlannerConfig::default())
+            .await
+            .unwrap();
+
+        // the workers are shared with the updated planner, which keeps using them
+        assert_eq!(
+            ShutdownOutcome::StillInUse,
+            planner.shutdown(Duration::from_secs(10)).await
+        );
+        updated_planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+
+        assert_eq!(
+            ShutdownOutcome::Exited,
+            updated_planner.shutdown(Duration::from_secs(10)).await
+        );
+    }
+
+    #[tokio::test]
+    async fn plan_batch() {
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap()
+                .with_plan_cache(NonZeroUsize::new(10).unwrap());
+
+        let results = planner
+            .plan_batch(vec![
+                (QUERY.to_string(), None, PlanOptions::default()),
+                ("{ unknownField }".to_string(), None, PlanOptions::default()),
+                (QUERY2.to_string(), None, PlanOptions::default()),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(3, results.len());
+
+        let mut results = results.into_iter().map(PlanResult::into_result);
+        let first = results.next().unwrap().unwrap();
+        assert!(results.next().unwrap().is_err());
+        let third = results.next().unwrap().unwrap();
+
+        // the successful plans were cached, and match what `plan` returns
+        let planned = planner
+            .plan(QUERY.to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(planned.data, first.data);
+        assert!(third.data.is_some());
+        assert_eq!(
+            Some(PlanCacheStats {
+                hits: 1,
+                misses: 3,
+                len: 2,
+                capacity: 10,
+            }),
+            planner.plan_cache_stats()
+        );
+    }
+
+    #[tokio::test]
+    async fn demand_cost() {
+        let planner = Planner::<serde_json::Value>::new(
+            DEMAND_CONTROL.to_string(),
+            QueryPlannerConfig::default(),
+        )
+        .await
+        .unwrap();
+        let options = DemandCostOptions {
+            default_list_size: 2,
+        };
+        let cost = |query: &str, variables: serde_json::Value| {
+            let variables = match variables {
+                serde_json::Value::Object(variables) => variables,
+                _ => panic!("variables must be an object"),
+            };
+            planner.demand_cost(query.to_string(), None, variables, options)
+        };
+
+        // 5 products of weight 2, each with a price of weight 5
+        let top_products = cost("{ topProducts { id price } }", serde_json::json!({}))
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .data;
+        assert_eq!(35.0, top_products.cost);
+        assert_eq!(
+            vec![
+                FieldCost {
+                    path: vec!["topProducts".to_string()],
+                    coordinate: "Query.topProducts".to_string(),
+                    cost: 35.0,
+                },
+                FieldCost {
+                    path: vec!["topProducts".to_string(), "id".to_string()],
+                    coordinate: "Product.id".to_string(),
+                    cost: 0.0,
+                },
+                FieldCost {
+                    path: vec!["topProducts".to_string(), "price".to_string()],
+                    coordinate: "Product.price".to_string(),
+                    cost: 5.0,
+                },
+            ],
+            top_products.fields
+        );
+
+        // the slicing argument comes from the variables, or from its default value,
+        // and the reviews have the default list size
+        let products =
+            "query Products($first: Int) { products(first: $first) { reviews { body } } }";
+        for (variables, expected) in [
+            (serde_json::json!({ "first": 3 }), 12.0),
+            (serde_json::json!({}), 40.0),
+        ] {
+            let estimate = cost(products, variables)
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap()
+                .data;
+            assert_eq!(expected, estimate.cost);
+        }
+
+        // the filter argument and its inStock field have a cost, and the items are sized by `first`
+        let search = cost(
+            "{ searchProducts(first: 4, filter: { inStock: true }) { total items { id } } }",
+            serde_json::json!({}),
+        )
+        .await
+        .unwrap()
+        .into_result()
+        .unwrap()
+        .data;
+        assert_eq!(16.0, search.cost);
+
+        let mutation = cost(
+            r#"mutation { addReview(body: "great") { body } }"#,
+            serde_json::json!({}),
+        )
+        .await
+        .unwrap()
+        .into_result()
+        .unwrap()
+        .data;
+        assert_eq!(11.0, mutation.cost);
+
+        assert!(cost("{ unknownField }", serde_json::json!({}))
+            .await
+            .unwrap()
+            .into_result()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn demand_cost_stops_at_fragment_cycles() {
+        let planner = Planner::<serde_json::Value>::new(
+            DEMAND_CONTROL.to_string(),
+            QueryPlannerConfig {
+                graphql_validation: false,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        // Without graphql-js validation, nothing rejects the cycle before the estimation
+        let estimate = planner
+            .demand_cost(
+                "\
+        fragment product1 on Product {
+            id
+            ...product2
+        }
+        fragment product2 on Product {
+            price
+            ...product1
+        }
+        query { topProducts { ...product1 } }"
+                    .to_string(),
+                None,
+                Default::default(),
+                DemandCostOptions {
+                    default_list_size: 1,
+                },
+            )
+            .await;
+        assert!(estimate.is_ok(), "{estimate:?}");
     }
 
-    async fn assert_errors(
-        expected_errors: Vec<PlanError>,
-        query: String,
-        operation_name: Option<String>,
-    ) {
+    #[tokio::test]
+    async fn demand_cost_requires_the_cost_spec() {
         let planner =
             Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
                 .await
                 .unwrap();
 
-        let actual = planner
-            .plan(query, operation_name, PlanOptions::default())
+        let errors = planner
+            .demand_cost(
+                QUERY.to_string(),
+                None,
+                Default::default(),
+                DemandCostOptions {
+                    default_list_size: 1,
+                },
+            )
             .await
-            .unwrap();
+            .unwrap()
+            .into_result()
+            .unwrap_err();
+        assert_eq!(
+            vec!["COST_SPEC_NOT_LINKED"],
+            errors
+                .errors
+                .iter()
+                .map(|error| error.extensions.as_ref().unwrap().code.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
 
-        assert_eq!(expected_errors, actual.errors.unwrap());
+    #[tokio::test]
+    async fn authorization_requirements() {
+        let planner = Planner::<serde_json::Value>::new(
+            AUTHORIZATION.to_string(),
+            QueryPlannerConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let requirements = planner
+            .authorization_requirements(
+                r#"query { me { email orders { id } ...orders } product(id: "1") { name } } fragment orders on User { orders { total } }"#.to_string(),
+                None,
+            )
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .data;
+        assert_eq!(
+            AuthorizationRequirements {
+                authenticated: vec!["Query.me".to_string()],
+                scopes: vec![Requirement {
+                    coordinate: "User.email".to_string(),
+                    any_of: vec![vec!["read:email".to_string()]],
+                }],
+                policies: vec![Requirement {
+                    coordinate: "Order".to_string(),
+                    any_of: vec![
+                        vec!["orders".to_string(), "admin".to_string()],
+                        vec!["support".to_string()],
+                    ],
+                }],
+            },
+            requirements
+        );
+
+        let public = planner
+            .authorization_requirements("{ publicInfo }".to_string(), None)
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .data;
+        assert!(public.is_empty());
+
+        // the requirements of the types implementing an interface, and of their fields,
+        // apply to selections on the interface
+        let documents = planner
+            .authorization_requirements("{ documents { id title } }".to_string(), None)
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .data;
+        assert_eq!(
+            AuthorizationRequirements {
+                authenticated: vec!["Invoice".to_string()],
+                scopes: vec![],
+                policies: vec![Requirement {
+                    coordinate: "Invoice.title".to_string(),
+                    any_of: vec![vec!["billing".to_string()]],
+                }],
+            },
+            documents
+        );
+
+        assert!(planner
+            .authorization_requirements("{ unknownField }".to_string(), None)
+            .await
+            .unwrap()
+            .into_result()
+            .is_err());
     }
 
     #[tokio::test]
-    async fn it_doesnt_race() {
+    async fn plan_with_timeout() {
         let planner =
             Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
                 .await
                 .unwrap();
 
-        let query_1_response = planner
+        let expected = planner
             .plan(QUERY.to_string(), None, PlanOptions::default())
             .await
             .unwrap()
             .data
             .unwrap();
 
-        let query_2_response = planner
-            .plan(QUERY2.to_string(), None, PlanOptions::default())
-            .await
-            .unwrap()
-            .data
-            .unwrap();
-
-        let all_futures = stream::iter((0..1000).map(|i| {
-            let (query, fut) = if i % 2 == 0 {
-                (
-                    QUERY,
-                    planner.plan(QUERY.to_string(), None, PlanOptions::default()),
+        // Whether the worker was interrupted or the request was still queued,
+        // the planner must remain usable.
+        for _ in 0..10 {
+            let timed_out = planner
+                .plan_with_timeout(
+                    QUERY2.to_string(),
+                    None,
+                    PlanOptions::default(),
+                    Duration::from_nanos(1),
                 )
-            } else {
-                (
-                    QUERY2,
-                    planner.plan(QUERY2.to_string(), None, PlanOptions::default()),
+                .await
+                .unwrap_err();
+            assert!(matches!(timed_out, crate::error::Error::Timeout(_)));
+
+            let actual = planner
+                .plan_with_timeout(
+                    QUERY.to_string(),
+                    None,
+                    PlanOptions::default(),
+                    Duration::from_secs(30),
                 )
-            };
+                .await
+                .unwrap()
+                .data
+                .unwrap();
+            assert_eq!(expected, actual);
+        }
+    }
 
-            async move { (query, fut.await.unwrap()) }
-        }));
+    #[tokio::test]
+    async fn plan_after_timeout() {
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
 
-        all_futures
-            .for_each_concurrent(None, |fut| async {
-                let (query, plan_result) = fut.await;
-                if query == QUERY {
-                    assert_eq!(query_1_response, plan_result.data.unwrap());
-                } else {
-                    assert_eq!(query_2_response, plan_result.data.unwrap());
-                }
-            })
-            .await;
+        let timed_out = planner
+            .plan_with_timeout(
+                QUERY2.to_string(),
+                None,
+                PlanOptions::default(),
+                Duration::from_nanos(1),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(timed_out, crate::error::Error::Timeout(_)));
+
+        // The same query isn't skipped by the worker, whether the timed out one was
+        // still queued, being planned or already answered
+        let planned = tokio::time::timeout(
+            Duration::from_secs(30),
+            planner.plan(QUERY2.to_string(), None, PlanOptions::default()),
+        )
+        .await
+        .expect("planning the same query again must complete")
+        .unwrap();
+        assert!(planned.data.is_some());
     }
 
     #[tokio::test]
@@ -1858,6 +3390,236 @@ ofType {
         insta::assert_snapshot!(signature);
     }
 
+    #[tokio::test]
+    async fn validate() {
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
+
+        let validated = planner
+            .validate(
+                MULTIPLE_QUERIES.to_string(),
+                Some("MyFirstName".to_string()),
+            )
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(
+            ValidatedOperation {
+                operation_name: Some("MyFirstName".to_string()),
+                operation_kind: OperationKind::Query,
+            },
+            validated.data
+        );
+
+        // the errors are the ones planning would fail with
+        for (query, operation_name) in [
+            ("{ me {", None),
+            ("{ unknownField }", None),
+            (MULTIPLE_QUERIES, None),
+            (MULTIPLE_QUERIES, Some("UnknownOperation")),
+        ] {
+            let operation_name = operation_name.map(str::to_string);
+            let validation_errors = planner
+                .validate(query.to_string(), operation_name.clone())
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap_err();
+            let plan_errors = planner
+                .plan(query.to_string(), operation_name, PlanOptions::default())
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap_err();
+            assert_eq!(plan_errors.errors, validation_errors.errors);
+            assert_eq!(
+                plan_errors.usage_reporting,
+                validation_errors.usage_reporting
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn normalized_operation() {
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
+        let normalize = |query: &str, operation_name: Option<&str>| {
+            let future =
+                planner.normalized_operation(query.to_string(), operation_name.map(str::to_string));
+            async move { future.await.unwrap().into_result().unwrap().data }
+        };
+
+        let normalized = normalize(
+            r#"
+            query Other { me { id } }
+            # the fragment is inlined, and the fields are sorted
+            query Me { me { ...name id } topProducts(first: 2) { upc } }
+            fragment name on User { name { last first } }
+            "#,
+            Some("Me"),
+        )
+        .await;
+        assert_eq!(
+            "query Me{me{id ...on User{name{first last}}}topProducts(first:2){upc}}",
+            normalized.document
+        );
+        assert_eq!(
+            "0555d6bc7161a6b2ddcc9a0f3c59c5e7bd1dfd638f3e2269521dfb46f415b927",
+            normalized.hash
+        );
+
+        // the same operation, written differently
+        let reordered = normalize(
+            "query Me { topProducts(first: 2) { upc } me { name { first last } id } }",
+            None,
+        )
+        .await;
+        assert_ne!(normalized.hash, reordered.hash);
+        let inlined = normalize(
+            "query Me { topProducts(first: 2) { upc } me { ... on User { name { last first } } id } }",
+            None,
+        )
+        .await;
+        assert_eq!(normalized, inlined);
+
+        assert!(planner
+            .normalized_operation("{ unknownField }".to_string(), None)
+            .await
+            .unwrap()
+            .into_result()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn persisted_queries() {
+        let manifest: PersistedQueryManifest = vec![
+            ("me".to_string(), "{ me { id } }".to_string()),
+            (
+                "reviews".to_string(),
+                "{ me { reviews { body } } }".to_string(),
+            ),
+            ("invalid".to_string(), "{ unknownField }".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap()
+                .with_persisted_queries(manifest)
+                .await
+                .unwrap();
+        let validation = planner.persisted_query_validation().unwrap();
+        assert_eq!(
+            vec!["invalid"],
+            validation.invalid.keys().collect::<Vec<_>>()
+        );
+
+        let planned = planner
+            .plan_persisted_query("me", None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let expected = planner
+            .plan("{ me { id } }".to_string(), None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(expected.data, planned.data);
+        assert!(matches!(
+            planner
+                .plan_persisted_query("unknown", None, PlanOptions::default())
+                .await,
+            Err(crate::error::Error::UnknownPersistedQuery { .. })
+        ));
+
+        // the manifest is validated against the new schema
+        let updated = planner
+            .update(
+                SCHEMA_WITHOUT_REVIEW_BODY.to_string(),
+                QueryPlannerConfig::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            vec!["invalid", "reviews"],
+            updated
+                .persisted_query_validation()
+                .unwrap()
+                .invalid
+                .keys()
+                .collect::<Vec<_>>()
+        );
+        assert!(updated
+            .plan_persisted_query("me", None, PlanOptions::default())
+            .await
+            .unwrap()
+            .into_result()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn coerce_variables() {
+        let planner =
+            Planner::<serde_json::Value>::new(SCHEMA.to_string(), QueryPlannerConfig::default())
+                .await
+                .unwrap();
+        let query = "query Vehicle($id: String!, $first: Int = 3, $format: Boolean) { vehicle(id: $id) { id } topReviews(first: $first) { body(format: $format) } }";
+        let coerce = |variables: serde_json::Value| {
+            let variables = match variables {
+                serde_json::Value::Object(variables) => variables,
+                _ => panic!("variables must be an object"),
+            };
+            planner.coerce_variables(query.to_string(), None, variables)
+        };
+
+        let coerced = coerce(serde_json::json!({ "id": "1" }))
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .data
+            .unwrap();
+        assert_eq!(
+            serde_json::json!({ "id": "1", "first": 3 }),
+            serde_json::Value::Object(coerced)
+        );
+
+        let errors = coerce(serde_json::json!({ "format": "yes" }))
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .data
+            .unwrap_err();
+        assert_eq!(
+            vec!["$.id", "$.format"],
+            errors
+                .iter()
+                .map(VariableError::json_path)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "VALIDATION_INVALID_TYPE_VARIABLE",
+            errors[0].extensions.as_ref().unwrap().code
+        );
+
+        assert!(planner
+            .coerce_variables("{ unknownField }".to_string(), None, Default::default())
+            .await
+            .unwrap()
+            .into_result()
+            .is_err());
+    }
+
     #[tokio::test]
     async fn subgraphs() {
         let planner =
@@ -2132,6 +3894,8 @@ feature https://specs.apollo.dev/unsupported-feature/v0.1 is for: SECURITY but i
                 reuse_query_fragments: None,
                 debug: Default::default(),
                 type_conditioned_fetching: false,
+                operation_limits: Default::default(),
+                planning_statistics: false,
             },
         )
         .await
@@ -2213,6 +3977,8 @@ feature https://specs.apollo.dev/unsupported-feature/v0.1 is for: SECURITY but i
                 reuse_query_fragments: None,
                 debug: Default::default(),
                 type_conditioned_fetching: false,
+                operation_limits: Default::default(),
+                planning_statistics: false,
             },
         )
         .await
@@ -2296,6 +4062,8 @@ feature https://specs.apollo.dev/unsupported-feature/v0.1 is for: SECURITY but i
                 generate_query_fragments: None,
                 debug: Default::default(),
                 type_conditioned_fetching: false,
+                operation_limits: Default::default(),
+                planning_statistics: false,
             },
         )
         .await
@@ -2332,6 +4100,8 @@ feature https://specs.apollo.dev/unsupported-feature/v0.1 is for: SECURITY but i
                 reuse_query_fragments: None,
                 debug: Default::default(),
                 type_conditioned_fetching: false,
+                operation_limits: Default::default(),
+                planning_statistics: false,
             },
         )
         .await
@@ -2388,6 +4158,8 @@ feature https://specs.apollo.dev/unsupported-feature/v0.1 is for: SECURITY but i
                 reuse_query_fragments: None,
                 debug: Default::default(),
                 type_conditioned_fetching: true,
+                operation_limits: Default::default(),
+                planning_statistics: false,
             },
         )
         .await
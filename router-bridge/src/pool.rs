@@ -0,0 +1,200 @@
+/*!
+# A pool of JavaScript workers sharing the same set of schemas.
+*/
+
+use crate::error::Error;
+use crate::heap::{HeapPolicy, HeapStatistics};
+use crate::queue::{QueuePolicy, QueueStatistics};
+use crate::worker::{JsWorker, SerializedRequest};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// A fixed size set of [`JsWorker`]s.
+///
+/// Commands that only need one worker are spread across the pool: the ones identical requests are
+/// coalesced for are sent to a worker picked from the command itself, and the others in a round
+/// robin fashion. Commands that change the state of a worker (such as schema updates) are broadcast
+/// to every worker.
+pub(crate) struct JsWorkerPool {
+    workers: Vec<JsWorker>,
+    next: AtomicUsize,
+}
+
+impl JsWorkerPool {
+    pub(crate) fn new(
+        worker_source_code: &'static str,
+        size: NonZeroUsize,
+        heap_policy: HeapPolicy,
+        queue_policy: QueuePolicy,
+    ) -> Self {
+        let workers = (0..size.get())
+            .map(|_| JsWorker::new(worker_source_code, heap_policy, queue_policy))
+            .collect();
+
+        Self {
+            workers,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Pick the next worker in line.
+    fn next_worker(&self) -> &JsWorker {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        &self.workers[index]
+    }
+
+    /// Send a request to a single worker of the pool and wait for its response.
+    ///
+    /// Identical requests are coalesced by the worker they are sent to,
+    /// so the worker is picked from a hash of the command: identical requests always share
+    /// one worker, and one computation.
+    pub(crate) async fn request<Request, Response>(
+        &self,
+        command: Request,
+    ) -> Result<Response, Error>
+    where
+        Request: Serialize + Send + Debug + 'static,
+        Response: DeserializeOwned + Send + Debug + 'static,
+    {
+        let request = SerializedRequest::new(command)?;
+        let index = (request.key_hash() % self.workers.len() as u64) as usize;
+        self.workers[index].request_serialized(request).await
+    }
+
+    /// Send a request to a single worker of the pool and wait for its response, for at most `timeout`.
+    ///
+    /// These requests aren't coalesced, so they are spread across the pool in a round robin fashion.
+    pub(crate) async fn request_with_timeout<Request, Response>(
+        &self,
+        command: Request,
+        timeout: Duration,
+    ) -> Result<Response, Error>
+    where
+        Request: Serialize + Send + Debug + 'static,
+        Response: DeserializeOwned + Send + Debug + 'static,
+    {
+        self.next_worker()
+            .request_with_timeout(command, timeout)
+            .await
+    }
+
+    /// Send a request to every worker of the pool, and wait for all of their responses.
+    ///
+    /// The requests are all sent before we start waiting for responses,
+    /// so the workers process them concurrently.
+    /// The responses are returned in the order of the workers in the pool.
+    pub(crate) async fn broadcast<Request, Response>(
+        &self,
+        command: Request,
+    ) -> Vec<Result<Response, Error>>
+    where
+        Request: Serialize + Clone + Send + Debug + 'static,
+        Response: DeserializeOwned + Send + Debug + 'static,
+    {
+        let mut sent = Vec::with_capacity(self.workers.len());
+        for worker in self.workers.iter() {
+            sent.push(worker.send(command.clone()).await);
+        }
+
+        let mut responses = Vec::with_capacity(self.workers.len());
+        for (worker, id) in self.workers.iter().zip(sent) {
+            responses.push(match id {
+                Ok(id) => worker.receive(id).await,
+                Err(e) => Err(e),
+            });
+        }
+        responses
+    }
+
+    /// Replay `command` on any worker of the pool that is respawned after a crash.
+    pub(crate) fn set_restart_command<Request>(
+        &self,
+        key: String,
+        command: &Request,
+    ) -> Result<(), Error>
+    where
+        Request: Serialize,
+    {
+        for worker in self.workers.iter() {
+            worker.set_restart_command(key.clone(), command)?;
+        }
+        Ok(())
+    }
+
+    /// Stop replaying the command set under `key` when a worker is respawned.
+    pub(crate) fn remove_restart_command(&self, key: &str) {
+        for worker in self.workers.iter() {
+            worker.remove_restart_command(key);
+        }
+    }
+
+    /// Take a snapshot of the request queue of every worker of the pool, in the order of the workers.
+    pub(crate) async fn queue_statistics(&self) -> Vec<QueueStatistics> {
+        let mut statistics = Vec::with_capacity(self.workers.len());
+        for worker in self.workers.iter() {
+            statistics.push(worker.queue_statistics().await);
+        }
+        statistics
+    }
+
+    /// Take a snapshot of the heap of every worker of the pool, in the order of the workers.
+    pub(crate) async fn heap_statistics(&self) -> Result<Vec<HeapStatistics>, Error> {
+        let mut statistics = Vec::with_capacity(self.workers.len());
+        for worker in self.workers.iter() {
+            statistics.push(worker.heap_statistics().await?);
+        }
+        Ok(statistics)
+    }
+
+    /// Stop accepting requests, and wait for every worker of the pool to process the queued ones
+    /// and exit, for at most `timeout`.
+    ///
+    /// Returns whether every worker exited cleanly in time.
+    pub(crate) async fn shutdown(self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let shutdowns = self
+            .workers
+            .into_iter()
+            .map(|worker| tokio::spawn(worker.shutdown(deadline)))
+            .collect::<Vec<_>>();
+
+        let mut exited_cleanly = true;
+        for shutdown in shutdowns {
+            exited_cleanly &= shutdown.await.unwrap_or(false);
+        }
+        exited_cleanly
+    }
+
+    /// Send a request to every worker of the pool, without waiting for a response.
+    pub(crate) async fn notify<Request>(&self, command: Request)
+    where
+        Request: Serialize + Clone + Send + Debug + 'static,
+    {
+        for worker in self.workers.iter() {
+            let _ = worker.notify(command.clone()).await;
+        }
+    }
+
+    /// Send a request to every worker of the pool, without waiting for a response,
+    /// or for room in their queues.
+    ///
+    /// The workers that can't take the request right away don't receive it, which is logged.
+    pub(crate) fn try_notify<Request>(&self, command: Request)
+    where
+        Request: Serialize + Clone + Send + Debug + 'static,
+    {
+        for worker in self.workers.iter() {
+            if let Err(e) = worker.try_notify(command.clone()) {
+                tracing::warn!("couldn't send {command:?} to a javascript worker: {e}");
+            }
+        }
+    }
+}
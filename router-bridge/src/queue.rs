@@ -0,0 +1,147 @@
+/*!
+# Queueing of the requests sent to the JavaScript workers.
+*/
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+/// How the requests waiting for a JavaScript worker are queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QueuePolicy {
+    /// The number of requests that can wait for each worker to pick them up
+    pub capacity: usize,
+    /// What to do with a request when the queue of its worker is full
+    pub when_full: QueueFullAction,
+    /// The number of responses kept for callers that stopped waiting for them,
+    /// so that an identical request can pick them up. The oldest ones are dropped first.
+    pub unsent_responses_capacity: usize,
+}
+
+/// What to do with a request when the queue of a worker reached the capacity of its [`QueuePolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueueFullAction {
+    /// Wait for the worker to catch up, and a slot to free up in the queue
+    Wait,
+    /// Fail the request with [`Error::QueueFull`](crate::error::Error::QueueFull).
+    ///
+    /// Requests nobody waits for the response of, such as dropping a schema, always wait.
+    Reject,
+}
+
+impl Default for QueuePolicy {
+    /// Up to 10 000 requests wait for a slot in the queue, and 1 000 unsent responses are kept.
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            when_full: QueueFullAction::Wait,
+            unsent_responses_capacity: 1_000,
+        }
+    }
+}
+
+/// A snapshot of the request queue of a JavaScript worker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStatistics {
+    /// The number of requests waiting for the worker to pick them up
+    pub queued: usize,
+    /// The number of requests waiting for a response, whether they are queued
+    /// or the worker is processing them. Coalesced requests count once.
+    pub in_flight: usize,
+    /// The number of responses kept for callers that stopped waiting for them
+    pub unsent_responses: usize,
+}
+
+/// Responses nobody received, by serialized command, up to a capacity.
+///
+/// Once the capacity is reached, the oldest responses are dropped first.
+pub(crate) struct UnsentResponses {
+    capacity: usize,
+    responses: HashMap<String, (u64, serde_json::Value)>,
+    /// The keys of the responses in insertion order, along with their insertion number,
+    /// so that the keys of responses that were removed or replaced since can be told apart
+    order: VecDeque<(u64, String)>,
+    inserted: u64,
+}
+
+impl UnsentResponses {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            responses: HashMap::new(),
+            order: VecDeque::new(),
+            inserted: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub(crate) fn insert(&mut self, key: String, response: serde_json::Value) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.inserted += 1;
+        self.responses
+            .insert(key.clone(), (self.inserted, response));
+        self.order.push_back((self.inserted, key));
+
+        while self.responses.len() > self.capacity {
+            match self.order.pop_front() {
+                Some((inserted, key)) => {
+                    if self.is_current(inserted, &key) {
+                        self.responses.remove(&key);
+                    }
+                }
+                None => break,
+            }
+        }
+        // Drop the keys of the responses that were removed, so they don't pile up
+        if self.order.len() > 2 * self.capacity {
+            let responses = &self.responses;
+            self.order.retain(|(inserted, key)| {
+                responses.get(key).map(|(current, _)| current) == Some(inserted)
+            });
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<serde_json::Value> {
+        self.responses.remove(key).map(|(_, response)| response)
+    }
+
+    fn is_current(&self, inserted: u64, key: &str) -> bool {
+        self.responses.get(key).map(|(current, _)| *current) == Some(inserted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn unsent_responses_are_capped() {
+        let mut unsent = UnsentResponses::new(2);
+        unsent.insert("a".to_string(), json!(1));
+        unsent.insert("b".to_string(), json!(2));
+        // replacing a response makes it the most recent one
+        unsent.insert("a".to_string(), json!(3));
+        unsent.insert("c".to_string(), json!(4));
+        assert_eq!(2, unsent.len());
+        assert_eq!(None, unsent.remove("b"));
+        assert_eq!(Some(json!(3)), unsent.remove("a"));
+        assert_eq!(Some(json!(4)), unsent.remove("c"));
+
+        // removed responses don't take up room
+        for i in 0..10 {
+            unsent.insert(i.to_string(), json!(i));
+            assert_eq!(Some(json!(i)), unsent.remove(&i.to_string()));
+        }
+        assert!(unsent.order.len() <= 4);
+
+        let mut disabled = UnsentResponses::new(0);
+        disabled.insert("a".to_string(), json!(1));
+        assert_eq!(0, disabled.len());
+    }
+}
@@ -0,0 +1,189 @@
+/*!
+# Aggregated usage of the schema by operations.
+
+[`UsageReporting`] describes a single operation. A [`UsageAggregate`] counts how many operations
+referenced each type and field, so as to tell which fields are unused and safe to deprecate.
+Aggregates can be serialized and merged, so that each router can send its own partial aggregate
+to a collector.
+*/
+
+use crate::planner::UsageReporting;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The number of operations that referenced each type and field, by operation signature.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageAggregate {
+    /// The usage, by `stats_report_key`
+    pub signatures: BTreeMap<String, SignatureUsage>,
+}
+
+/// The usage of the operations that share a signature.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureUsage {
+    /// The number of operations recorded with this signature
+    pub operation_count: u64,
+    /// The usage of each type referenced by the operations, by type name
+    pub types: BTreeMap<String, TypeUsage>,
+}
+
+/// The usage of a type by operations.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeUsage {
+    /// The number of operations that referenced the type
+    pub count: u64,
+    /// Whether the type is an interface
+    pub is_interface: bool,
+    /// The number of operations that referenced each field of the type, by field name
+    pub fields: BTreeMap<String, u64>,
+}
+
+impl UsageAggregate {
+    /// Create an empty aggregate
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the usage of an operation
+    pub fn record(&mut self, usage: &UsageReporting) {
+        let signature = self
+            .signatures
+            .entry(usage.stats_report_key.clone())
+            .or_default();
+        signature.operation_count += 1;
+
+        for (type_name, referenced) in &usage.referenced_fields_by_type {
+            let type_usage = signature.types.entry(type_name.clone()).or_default();
+            type_usage.count += 1;
+            type_usage.is_interface |= referenced.is_interface;
+            for field_name in &referenced.field_names {
+                *type_usage.fields.entry(field_name.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Add the counts of another aggregate to this one
+    pub fn merge(&mut self, other: UsageAggregate) {
+        for (key, other_signature) in other.signatures {
+            let signature = self.signatures.entry(key).or_default();
+            signature.operation_count += other_signature.operation_count;
+
+            for (type_name, other_type) in other_signature.types {
+                let type_usage = signature.types.entry(type_name).or_default();
+                type_usage.count += other_type.count;
+                type_usage.is_interface |= other_type.is_interface;
+                for (field_name, count) in other_type.fields {
+                    *type_usage.fields.entry(field_name).or_default() += count;
+                }
+            }
+        }
+    }
+
+    /// The number of operations that referenced each field, across signatures,
+    /// by schema coordinate (`Type.field`)
+    pub fn field_counts(&self) -> BTreeMap<String, u64> {
+        let mut counts = BTreeMap::new();
+        for signature in self.signatures.values() {
+            for (type_name, type_usage) in &signature.types {
+                for (field_name, count) in &type_usage.fields {
+                    *counts
+                        .entry(format!("{type_name}.{field_name}"))
+                        .or_default() += count;
+                }
+            }
+        }
+        counts
+    }
+
+    /// The number of operations that referenced each type, across signatures, by type name
+    pub fn type_counts(&self) -> BTreeMap<String, u64> {
+        let mut counts = BTreeMap::new();
+        for signature in self.signatures.values() {
+            for (type_name, type_usage) in &signature.types {
+                *counts.entry(type_name.clone()).or_default() += type_usage.count;
+            }
+        }
+        counts
+    }
+}
+
+impl<'a> Extend<&'a UsageReporting> for UsageAggregate {
+    fn extend<I: IntoIterator<Item = &'a UsageReporting>>(&mut self, iter: I) {
+        for usage in iter {
+            self.record(usage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planner::ReferencedFieldsForType;
+
+    fn usage(key: &str, types: &[(&str, &[&str])]) -> UsageReporting {
+        UsageReporting {
+            stats_report_key: key.to_string(),
+            referenced_fields_by_type: types
+                .iter()
+                .map(|(type_name, fields)| {
+                    (
+                        type_name.to_string(),
+                        ReferencedFieldsForType {
+                            field_names: fields.iter().map(ToString::to_string).collect(),
+                            is_interface: false,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn record_and_merge() {
+        let me = usage(
+            "# Me\n{me{id name}}",
+            &[("Query", &["me"]), ("User", &["id", "name"])],
+        );
+        let reviews = usage(
+            "# Reviews\n{me{reviews{body}}}",
+            &[
+                ("Query", &["me"]),
+                ("User", &["reviews"]),
+                ("Review", &["body"]),
+            ],
+        );
+
+        let mut first = UsageAggregate::new();
+        first.extend([&me, &me].iter().copied());
+        let mut second = UsageAggregate::new();
+        second.record(&reviews);
+
+        // partial aggregates are sent to the collector serialized
+        let second: UsageAggregate =
+            serde_json::from_str(&serde_json::to_string(&second).unwrap()).unwrap();
+        first.merge(second);
+
+        assert_eq!(2, first.signatures["# Me\n{me{id name}}"].operation_count);
+        assert_eq!(
+            BTreeMap::from([
+                ("Query.me".to_string(), 3),
+                ("Review.body".to_string(), 1),
+                ("User.id".to_string(), 2),
+                ("User.name".to_string(), 2),
+                ("User.reviews".to_string(), 1),
+            ]),
+            first.field_counts()
+        );
+        assert_eq!(
+            BTreeMap::from([
+                ("Query".to_string(), 3),
+                ("Review".to_string(), 1),
+                ("User".to_string(), 3),
+            ]),
+            first.type_counts()
+        );
+    }
+}
@@ -0,0 +1,223 @@
+/*!
+# Encoding of usage reports in the Apollo usage reporting protobuf format.
+
+A [`UsageAggregate`] is encoded as a `Report` message, as defined by
+[`reports.proto`](https://github.com/apollographql/apollo-server/blob/main/packages/usage-reporting-protobuf/src/reports.proto),
+so that it can be sent to any ingester of that format.
+
+Only the fields that an aggregate can fill are encoded:
+- `Report.header`, `Report.end_time` and `Report.operation_count`
+- `TracesAndStats.referenced_fields_by_type`, for each signature
+- `QueryLatencyStats.request_count`, for each signature
+*/
+
+use crate::usage_aggregate::UsageAggregate;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The `ReportHeader` of a usage report, describing where the report comes from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportHeader {
+    /// The graph the operations ran against, as `graph@variant`
+    pub graph_ref: String,
+    /// The host that produced the report
+    pub hostname: String,
+    /// The name and version of the agent that produced the report
+    pub agent_version: String,
+    /// The version of the service that produced the report
+    pub service_version: String,
+    /// The runtime the agent runs on
+    pub runtime_version: String,
+    /// The output of `uname -a`
+    pub uname: String,
+    /// The hash of the API schema the operations ran against
+    pub executable_schema_id: String,
+}
+
+impl UsageAggregate {
+    /// Encode the aggregate as a protobuf `Report` message, covering operations until `end_time`
+    pub fn encode_report(&self, header: &ReportHeader, end_time: SystemTime) -> Vec<u8> {
+        let mut report = Message::default();
+        report.message(1, &encode_header(header));
+        report.message(2, &encode_timestamp(end_time));
+
+        for (key, signature) in &self.signatures {
+            let mut traces_and_stats = Message::default();
+
+            let mut latency_stats = Message::default();
+            latency_stats.uint64(2, signature.operation_count);
+            let mut stats_with_context = Message::default();
+            stats_with_context.message(2, &latency_stats.bytes);
+            traces_and_stats.message(2, &stats_with_context.bytes);
+
+            for (type_name, type_usage) in &signature.types {
+                let mut referenced_fields = Message::default();
+                for field_name in type_usage.fields.keys() {
+                    referenced_fields.string(1, field_name);
+                }
+                referenced_fields.bool(2, type_usage.is_interface);
+                traces_and_stats.map_entry(4, type_name, &referenced_fields.bytes);
+            }
+
+            report.map_entry(5, key, &traces_and_stats.bytes);
+        }
+
+        report.uint64(
+            6,
+            self.signatures
+                .values()
+                .map(|signature| signature.operation_count)
+                .sum(),
+        );
+        report.bytes
+    }
+}
+
+fn encode_header(header: &ReportHeader) -> Vec<u8> {
+    let mut message = Message::default();
+    message.string(5, &header.hostname);
+    message.string(6, &header.agent_version);
+    message.string(7, &header.service_version);
+    message.string(8, &header.runtime_version);
+    message.string(9, &header.uname);
+    message.string(11, &header.executable_schema_id);
+    message.string(12, &header.graph_ref);
+    message.bytes
+}
+
+// google.protobuf.Timestamp
+fn encode_timestamp(time: SystemTime) -> Vec<u8> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut message = Message::default();
+    message.uint64(1, since_epoch.as_secs());
+    message.uint64(2, since_epoch.subsec_nanos().into());
+    message.bytes
+}
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_LEN: u64 = 2;
+
+/// A protobuf message being encoded.
+///
+/// As in proto3, scalar fields holding their default value are not written.
+#[derive(Default)]
+struct Message {
+    bytes: Vec<u8>,
+}
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn tag(&mut self, field: u32, wire_type: u64) {
+        self.varint(u64::from(field) << 3 | wire_type);
+    }
+
+    fn uint64(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.tag(field, WIRE_TYPE_VARINT);
+            self.varint(value);
+        }
+    }
+
+    fn bool(&mut self, field: u32, value: bool) {
+        self.uint64(field, value.into());
+    }
+
+    fn len_delimited(&mut self, field: u32, value: &[u8]) {
+        self.tag(field, WIRE_TYPE_LEN);
+        self.varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        if !value.is_empty() {
+            self.len_delimited(field, value.as_bytes());
+        }
+    }
+
+    // Unlike scalars, embedded messages are written even if empty, so that they are present
+    fn message(&mut self, field: u32, value: &[u8]) {
+        self.len_delimited(field, value);
+    }
+
+    // A `map<string, Message>` entry is an embedded message with the key as field 1,
+    // and the value as field 2
+    fn map_entry(&mut self, field: u32, key: &str, value: &[u8]) {
+        let mut entry = Message::default();
+        entry.string(1, key);
+        entry.message(2, value);
+        self.message(field, &entry.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage_aggregate::{SignatureUsage, TypeUsage};
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[test]
+    fn varint() {
+        let mut message = Message::default();
+        message.varint(1);
+        message.varint(150);
+        message.varint(u64::MAX);
+        assert_eq!(
+            vec![0x01, 0x96, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            message.bytes
+        );
+    }
+
+    #[test]
+    fn encode_report() {
+        let aggregate = UsageAggregate {
+            signatures: BTreeMap::from([(
+                "# -\n{me}".to_string(),
+                SignatureUsage {
+                    operation_count: 3,
+                    types: BTreeMap::from([(
+                        "Query".to_string(),
+                        TypeUsage {
+                            count: 3,
+                            is_interface: false,
+                            fields: BTreeMap::from([("me".to_string(), 3)]),
+                        },
+                    )]),
+                },
+            )]),
+        };
+        let header = ReportHeader {
+            graph_ref: "g@v".to_string(),
+            ..Default::default()
+        };
+
+        let bytes = aggregate.encode_report(&header, UNIX_EPOCH + Duration::from_millis(1_500));
+
+        #[rustfmt::skip]
+        let expected: Vec<u8> = vec![
+            // header
+            0x0a, 0x05, 0x62, 0x03, b'g', b'@', b'v',
+            // end_time: 1s and 500_000_000ns
+            0x12, 0x08, 0x08, 0x01, 0x10, 0x80, 0xca, 0xb5, 0xee, 0x01,
+            // traces_per_query
+            0x2a, 0x21,
+                0x0a, 0x08, b'#', b' ', b'-', b'\n', b'{', b'm', b'e', b'}',
+                0x12, 0x15,
+                    // stats_with_context
+                    0x12, 0x04, 0x12, 0x02, 0x10, 0x03,
+                    // referenced_fields_by_type, where is_interface is false and not written
+                    0x22, 0x0d,
+                        0x0a, 0x05, b'Q', b'u', b'e', b'r', b'y',
+                        0x12, 0x04, 0x0a, 0x02, b'm', b'e',
+            // operation_count
+            0x30, 0x03,
+        ];
+        assert_eq!(expected, bytes);
+    }
+}
@@ -0,0 +1,70 @@
+/*!
+# Coercion of the variables of operations.
+
+Variables are coerced against the API schema with the GraphQL input coercion rules,
+so that invalid payloads are rejected before the operation is executed.
+See [`Planner::coerce_variables`](crate::planner::Planner::coerce_variables).
+*/
+
+use crate::planner::PlanErrorExtensions;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// The variables of an operation, once coerced, or the reasons they couldn't be.
+pub type CoercedVariables = Result<serde_json::Map<String, serde_json::Value>, Vec<VariableError>>;
+
+/// A variable value that doesn't match its type.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VariableError {
+    /// The error message
+    pub message: String,
+    /// The path of the invalid value in the variables object, starting with the variable name
+    pub path: Vec<PathElement>,
+    /// The error code
+    pub extensions: Option<PlanErrorExtensions>,
+}
+
+/// An element of the path of a value in a JSON object.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum PathElement {
+    /// An index in a list
+    Index(usize),
+    /// A key in an object
+    Key(String),
+}
+
+impl Display for PathElement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathElement::Index(index) => write!(f, "[{index}]"),
+            PathElement::Key(key) => write!(f, ".{key}"),
+        }
+    }
+}
+
+impl VariableError {
+    /// The path of the invalid value as a JSONPath expression, like `$.input.items[0].name`
+    pub fn json_path(&self) -> String {
+        std::iter::once("$".to_string())
+            .chain(self.path.iter().map(ToString::to_string))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_path() {
+        let error: VariableError = serde_json::from_value(serde_json::json!({
+            "message": "invalid",
+            "path": ["input", "items", 0, "name"],
+            "extensions": { "code": "VALIDATION_INVALID_TYPE_VARIABLE" }
+        }))
+        .unwrap();
+        assert_eq!("$.input.items[0].name", error.json_path());
+    }
+}
@@ -1,7 +1,10 @@
 use crate::error::Error;
-use async_channel::{bounded, Receiver, Sender};
+use crate::heap::{heap_statistics, HeapPolicy, HeapStatistics};
+use crate::js::Js;
+use crate::queue::{QueueFullAction, QueuePolicy, QueueStatistics, UnsentResponses};
+use async_channel::{bounded, Receiver, Sender, TrySendError};
 use deno_core::Op;
-use deno_core::{op, Extension, OpState};
+use deno_core::{op, v8, Extension, OpState};
 use rand::rngs::StdRng;
 use rand::{thread_rng, Rng};
 use serde::de::DeserializeOwned;
@@ -10,52 +13,180 @@ use serde::Serialize;
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::fmt::Debug;
-use std::hash::Hasher;
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
-use std::sync::Arc;
-use std::thread::JoinHandle;
-use tokio::sync::{oneshot, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Mutex};
 
-#[derive(Serialize, Deserialize, Debug)]
+// The id of the commands replayed after a restart, which nobody waits for.
+const RESTART_ID_PREFIX: &str = "restart:";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct JsonPayload {
     id: String,
     payload: serde_json::Value,
 }
 
+/// Keeps track of the request the JavaScript worker is processing,
+/// so it can be interrupted if it takes too long.
+///
+/// This is shared between the [`JsWorker`] and the `send` and `receive` ops.
+#[derive(Default)]
+struct Inflight {
+    isolate: Option<v8::IsolateHandle>,
+    /// The request the worker received, and hasn't responded to yet
+    current: Option<JsonPayload>,
+    /// Requests that must be handed to the worker again after a restart
+    replay: VecDeque<JsonPayload>,
+    /// Requests someone waits for, that the worker didn't pick up yet
+    queued: HashSet<String>,
+    /// Queued requests the worker must skip
+    cancelled: HashSet<String>,
+    /// The request we terminated the isolate for
+    terminated: Option<String>,
+    /// The span of each request that hasn't been responded to,
+    /// so the logs of the worker can be emitted in the span of the request it is processing
+    spans: HashMap<String, RequestSpan>,
+}
+
+impl Inflight {
+    /// The span of the request the worker is processing
+    fn current_span(&self) -> RequestSpan {
+        self.current
+            .as_ref()
+            .and_then(|current| self.spans.get(&current.id))
+            .cloned()
+            .unwrap_or_else(|| RequestSpan {
+                span: tracing::Span::none(),
+                schema_id: None,
+            })
+    }
+}
+
+/// Where the logs of the worker are emitted while it processes a request
+#[derive(Clone)]
+struct RequestSpan {
+    span: tracing::Span,
+    /// The schema the request is for.
+    ///
+    /// It is read from the request before it is sent, as javascript numbers can't hold every `u64`.
+    schema_id: Option<u64>,
+}
+
+// The worker responds with a payload, or with an error if it failed to process the request.
+type ResponseSender = oneshot::Sender<Result<serde_json::Value, Error>>;
+
+type ResponseSenders = Arc<Mutex<Responders>>;
+
+/// The callers waiting for the responses of the worker.
+#[derive(Default)]
+struct Responders {
+    /// The callers waiting for each request, by request id
+    waiting: HashMap<String, Waiters>,
+    /// The id of the request in flight for each coalesced command, by serialized command
+    coalesced: HashMap<String, String>,
+}
+
+/// The callers waiting for the response to a request.
+struct Waiters {
+    /// The serialized command, if identical requests are coalesced into this one
+    key: Option<String>,
+    senders: Vec<ResponseSender>,
+}
+
+impl Responders {
+    /// Stop waiting for the response to the request `id`, returning who was waiting for it.
+    fn take(&mut self, id: &str) -> Option<Waiters> {
+        let waiters = self.waiting.remove(id)?;
+        if let Some(key) = &waiters.key {
+            self.coalesced.remove(key);
+        }
+        Some(waiters)
+    }
+}
+
+impl Waiters {
+    /// Hand a response to every waiter, returning whether anyone received it.
+    fn respond(self, response: Result<serde_json::Value, Error>) -> bool {
+        let mut received = false;
+        for sender in self.senders {
+            received |= sender.send(response.clone()).is_ok();
+        }
+        received
+    }
+}
+
 pub(crate) struct JsWorker {
-    response_senders: Arc<Mutex<HashMap<String, oneshot::Sender<serde_json::Value>>>>,
-    response_receivers: Arc<Mutex<HashMap<String, oneshot::Receiver<serde_json::Value>>>>,
+    /// Request ids are unique to a worker, and never reused
+    next_id: AtomicU64,
+    response_senders: ResponseSenders,
+    response_receivers:
+        Arc<Mutex<HashMap<String, oneshot::Receiver<Result<serde_json::Value, Error>>>>>,
     sender: Sender<JsonPayload>,
-    handle: Option<JoinHandle<()>>,
-    unsent_plans: Arc<Mutex<HashMap<String, serde_json::Value>>>,
+    queue_policy: QueuePolicy,
+    /// Resolves once the worker thread is done, with whether the javascript runtime exited cleanly
+    exited: oneshot::Receiver<bool>,
+    unsent_plans: Arc<Mutex<UnsentResponses>>,
+    inflight: Arc<std::sync::Mutex<Inflight>>,
+    restart_commands: Arc<std::sync::Mutex<HashMap<String, serde_json::Value>>>,
+    heap_statistics_sender: mpsc::UnboundedSender<oneshot::Sender<HeapStatistics>>,
 }
 
 impl JsWorker {
-    pub(crate) fn new(worker_source_code: &'static str) -> Self {
-        let response_senders: Arc<Mutex<HashMap<String, oneshot::Sender<serde_json::Value>>>> =
-            Default::default();
+    pub(crate) fn new(
+        worker_source_code: &'static str,
+        heap_policy: HeapPolicy,
+        queue_policy: QueuePolicy,
+    ) -> Self {
+        let response_senders: ResponseSenders = Default::default();
 
         let cloned_senders = response_senders.clone();
+        let thread_senders = response_senders.clone();
 
-        let (response_sender, receiver) = bounded::<JsonPayload>(10_000);
-        let (sender, request_receiver) = bounded::<JsonPayload>(10_000);
+        // A bounded channel can't have a capacity of 0
+        let capacity = queue_policy.capacity.max(1);
+        let (response_sender, receiver) = bounded::<JsonPayload>(capacity);
+        let (sender, request_receiver) = bounded::<JsonPayload>(capacity);
 
-        let unsent_plans = Arc::new(Mutex::new(HashMap::new()));
+        let unsent_plans = Arc::new(Mutex::new(UnsentResponses::new(
+            queue_policy.unsent_responses_capacity,
+        )));
         let my_unsent_plans = unsent_plans.clone();
 
+        let inflight: Arc<std::sync::Mutex<Inflight>> = Default::default();
+        let my_inflight = inflight.clone();
+
         tokio::spawn(async move {
             while let Ok(json_payload) = receiver.recv().await {
-                if let Some(sender) = cloned_senders.lock().await.remove(&json_payload.id) {
-                    if let Err(e) = sender.send(json_payload.payload.clone()) {
+                if json_payload.id.starts_with(RESTART_ID_PREFIX) {
+                    tracing::debug!(
+                        "jsworker: replayed command {} after a restart: {}",
+                        &json_payload.id,
+                        json_payload.payload
+                    );
+                    continue;
+                }
+                let waiters = cloned_senders.lock().await.take(&json_payload.id);
+                if let Some(waiters) = waiters {
+                    let key = waiters.key.clone();
+                    if !waiters.respond(Ok(json_payload.payload.clone())) {
+                        tracing::error!(
+                            "jsworker: couldn't send json response for payload id {}",
+                            &json_payload.id
+                        );
                         // Keep our plan in our failed plan cache. Someone else might want it.
-                        tracing::error!("jsworker: couldn't send json response: {:?}", e);
-                        my_unsent_plans
-                            .lock()
-                            .await
-                            .insert(json_payload.id, json_payload.payload);
+                        if let Some(key) = key {
+                            my_unsent_plans
+                                .lock()
+                                .await
+                                .insert(key, json_payload.payload);
+                        }
                     }
                 } else {
                     tracing::error!(
@@ -67,126 +198,407 @@ impl JsWorker {
             tracing::debug!("deno runtime shutdown successfully");
         });
 
-        let handle = std::thread::spawn(move || {
-            let my_ext = Extension {
-                name: concat!(env!("CARGO_PKG_NAME"), "_worker"),
-                ops: Cow::Borrowed(&[
-                    send::DECL,
-                    receive::DECL,
-                    log_trace::DECL,
-                    log_debug::DECL,
-                    log_info::DECL,
-                    log_warn::DECL,
-                    log_error::DECL,
-                    op_crypto_get_random_values::DECL,
-                ]),
-                op_state_fn: Some(Box::new(move |state| {
-                    state.put(response_sender.clone());
-                    state.put(request_receiver);
-                })),
-                ..Default::default()
-            };
+        let restart_commands: Arc<std::sync::Mutex<HashMap<String, serde_json::Value>>> =
+            Default::default();
+        let my_restart_commands = restart_commands.clone();
 
-            let mut js_runtime =
-                crate::js::Js::new("query planner".to_string()).build_js_runtime(my_ext);
+        let (heap_statistics_sender, mut heap_statistics_receiver) = mpsc::unbounded_channel();
+        let heap_expansions: Arc<AtomicUsize> = Default::default();
 
-            let runtime = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
+        let (exit_sender, exited) = oneshot::channel();
+        // The thread is detached, it exits once the worker processed the requests sent to it
+        // and nobody can send new ones. See `JsWorker::shutdown`.
+        std::thread::spawn(move || {
+            let exited_cleanly = loop {
+                let my_ext = Extension {
+                    name: concat!(env!("CARGO_PKG_NAME"), "_worker"),
+                    ops: Cow::Borrowed(&[
+                        send::DECL,
+                        receive::DECL,
+                        log_trace::DECL,
+                        log_debug::DECL,
+                        log_info::DECL,
+                        log_warn::DECL,
+                        log_error::DECL,
+                        op_crypto_get_random_values::DECL,
+                        monotonic_now::DECL,
+                    ]),
+                    op_state_fn: Some(Box::new({
+                        let response_sender = response_sender.clone();
+                        let request_receiver = request_receiver.clone();
+                        let inflight = my_inflight.clone();
+                        move |state| {
+                            state.put(response_sender);
+                            state.put(request_receiver);
+                            state.put(inflight);
+                        }
+                    })),
+                    ..Default::default()
+                };
 
-            let future = async move {
-                js_runtime
-                    .execute_script_static("worker.js", worker_source_code)
-                    .unwrap();
-                js_runtime.run_event_loop(false).await
+                let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    run_js_runtime(
+                        worker_source_code,
+                        my_ext,
+                        heap_policy,
+                        &thread_senders,
+                        &my_inflight,
+                        &mut heap_statistics_receiver,
+                        &heap_expansions,
+                    )
+                }));
+
+                let error = match outcome {
+                    Ok(Ok(())) => break true,
+                    // Nobody can send requests to a new worker anymore.
+                    _ if request_receiver.is_closed() => break false,
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => "the javascript runtime panicked".to_string(),
+                };
+
+                // The request the worker was processing might be what made it crash,
+                // so rather than handing it to the new worker, we let its caller know it failed.
+                let failed_request = {
+                    let mut inflight = my_inflight.lock().expect("inflight lock poisoned");
+                    inflight.isolate = None;
+                    inflight.terminated = None;
+                    let failed_request = inflight.current.take();
+                    if let Some(failed_request) = &failed_request {
+                        inflight.spans.remove(&failed_request.id);
+                    }
+                    failed_request
+                };
+                if let Some(failed_request) = failed_request {
+                    let waiters = thread_senders.blocking_lock().take(&failed_request.id);
+                    if let Some(waiters) = waiters {
+                        waiters.respond(Err(Error::DenoRuntime(format!(
+                            "the javascript worker crashed: `{error}`"
+                        ))));
+                    }
+                }
+
+                let restart_commands = my_restart_commands
+                    .lock()
+                    .expect("restart commands lock poisoned")
+                    .iter()
+                    .map(|(key, payload)| JsonPayload {
+                        id: format!("{RESTART_ID_PREFIX}{key}"),
+                        payload: payload.clone(),
+                    })
+                    .collect::<Vec<_>>();
+
+                tracing::error!(
+                "jsworker: the javascript worker crashed: `{error}`, respawning it and replaying {} command(s)",
+                restart_commands.len()
+            );
+
+                my_inflight
+                    .lock()
+                    .expect("inflight lock poisoned")
+                    .replay
+                    .extend(restart_commands);
             };
-            runtime.block_on(future).unwrap();
+            let _ = exit_sender.send(exited_cleanly);
         });
 
         Self {
+            next_id: AtomicU64::new(0),
             sender,
-            handle: Some(handle),
+            queue_policy,
+            exited,
             response_receivers: Default::default(),
             response_senders,
             unsent_plans,
+            inflight,
+            restart_commands,
+            heap_statistics_sender,
         }
     }
 
-    pub(crate) async fn request<Request, Response>(
+    /// Take a snapshot of the heap of the worker.
+    ///
+    /// The snapshot is taken in between two requests, so it waits for the current one to complete.
+    pub(crate) async fn heap_statistics(&self) -> Result<HeapStatistics, Error> {
+        let (sender, receiver) = oneshot::channel();
+        self.heap_statistics_sender.send(sender).map_err(|e| {
+            Error::DenoRuntime(format!("heap_statistics: couldn't send request {e}"))
+        })?;
+        receiver.await.map_err(|e| {
+            Error::DenoRuntime(format!("heap_statistics: couldn't receive response: {e:?}"))
+        })
+    }
+
+    /// Replay `command` when the worker is respawned after a crash.
+    ///
+    /// Setting a command under an existing `key` replaces the previous one.
+    pub(crate) fn set_restart_command<Request>(
         &self,
+        key: String,
         command: Request,
+    ) -> Result<(), Error>
+    where
+        Request: Serialize,
+    {
+        let payload = serde_json::to_value(command).map_err(|e| Error::ParameterSerialization {
+            message: format!("deno: couldn't serialize request : `{e:?}`"),
+            name: "request".to_string(),
+        })?;
+        self.restart_commands
+            .lock()
+            .expect("restart commands lock poisoned")
+            .insert(key, payload);
+        Ok(())
+    }
+
+    /// Stop replaying the command set under `key` when the worker is respawned.
+    pub(crate) fn remove_restart_command(&self, key: &str) {
+        self.restart_commands
+            .lock()
+            .expect("restart commands lock poisoned")
+            .remove(key);
+    }
+
+    /// Send a request and wait for its response, for at most `timeout`.
+    ///
+    /// If the timeout elapses, the request is abandoned:
+    /// it is skipped if the worker didn't pick it up yet,
+    /// and the worker is interrupted if it is processing it.
+    pub(crate) async fn request_with_timeout<Request, Response>(
+        &self,
+        command: Request,
+        timeout: Duration,
     ) -> Result<Response, Error>
     where
-        Request: std::hash::Hash + Serialize + Send + Debug + 'static,
+        Request: Serialize + Send + Debug + 'static,
         Response: DeserializeOwned + Send + Debug + 'static,
     {
-        // Let's see if we already have this query plan in our failed delivery cache
-        let mut hasher = DefaultHasher::new();
-        command.hash(&mut hasher);
-        // JavaScript can't process 64 bit numbers, so convert our hash to a string...
-        let id = hasher.finish().to_string();
-
-        if let Some(payload) = self.unsent_plans.lock().await.remove(&id) {
-            serde_json::from_value(payload).map_err(|e| Error::ParameterDeserialization {
-                message: format!("deno: couldn't deserialize response : `{e:?}`"),
-                id,
-            })
-        } else {
-            self.send(Some(id.clone()), command)
-                .await
-                .map_err(|e| Error::DenoRuntime(format!("couldn't send request {e}")))?;
-            self.receive(id)
-                .await
-                .map_err(|e| Error::DenoRuntime(format!("request: couldn't receive response {e}")))
+        let id = self.send(command).await?;
+
+        match tokio::time::timeout(timeout, self.receive(id.clone())).await {
+            Ok(response) => response,
+            Err(_) => {
+                self.cancel(&id).await;
+                Err(Error::Timeout(timeout))
+            }
+        }
+    }
+
+    /// Abandon a request we won't wait the response for.
+    async fn cancel(&self, id: &str) {
+        self.response_senders.lock().await.take(id);
+        self.response_receivers.lock().await.remove(id);
+
+        let mut inflight = self.inflight.lock().expect("inflight lock poisoned");
+        inflight.spans.remove(id);
+        if inflight.current.as_ref().map(|current| current.id.as_str()) == Some(id) {
+            tracing::debug!("jsworker: terminating the isolate running request {id}");
+            inflight.terminated = Some(id.to_string());
+            if let Some(isolate) = &inflight.isolate {
+                isolate.terminate_execution();
+            }
+        } else if inflight.queued.contains(id)
+            || inflight.replay.iter().any(|request| request.id == id)
+        {
+            inflight.cancelled.insert(id.to_string());
         }
+        // Otherwise the response came in right after the timeout, and there is nothing left to skip
+    }
+
+    /// Send a request and wait for its response.
+    ///
+    /// Identical requests are coalesced: while a request is in flight,
+    /// the same command waits for its response rather than being sent again.
+    pub(crate) async fn request<Request, Response>(
+        &self,
+        command: Request,
+    ) -> Result<Response, Error>
+    where
+        Request: Serialize + Send + Debug + 'static,
+        Response: DeserializeOwned + Send + Debug + 'static,
+    {
+        self.request_serialized(SerializedRequest::new(command)?)
+            .await
     }
 
-    pub(crate) async fn send<Request>(
+    /// Like [`JsWorker::request`], for a command that was already serialized.
+    pub(crate) async fn request_serialized<Response>(
         &self,
-        id_opt: Option<String>,
-        request: Request,
-    ) -> Result<String, Error>
+        request: SerializedRequest,
+    ) -> Result<Response, Error>
     where
-        Request: std::hash::Hash + Serialize + Send + Debug + 'static,
+        Response: DeserializeOwned + Send + Debug + 'static,
     {
-        let id = match id_opt {
-            Some(id) => id,
-            None => {
-                let mut hasher = DefaultHasher::new();
-                request.hash(&mut hasher);
-                // JavaScript can't process 64 bit numbers, so convert our hash to a string...
-                hasher.finish().to_string()
+        let SerializedRequest { payload, key } = request;
+
+        // Let's see if we already have this query plan in our failed delivery cache
+        if let Some(payload) = self.unsent_plans.lock().await.remove(&key) {
+            return deserialize_response(payload, key);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let (id, in_flight) = {
+            let mut responders = self.response_senders.lock().await;
+            match responders.coalesced.get(&key).cloned() {
+                Some(id) => {
+                    responders
+                        .waiting
+                        .get_mut(&id)
+                        .expect("coalesced requests have waiters")
+                        .senders
+                        .push(sender);
+                    (id, true)
+                }
+                None => {
+                    let id = self.next_id();
+                    responders.coalesced.insert(key.clone(), id.clone());
+                    responders.waiting.insert(
+                        id.clone(),
+                        Waiters {
+                            key: Some(key),
+                            senders: vec![sender],
+                        },
+                    );
+                    (id, false)
+                }
             }
         };
 
+        if !in_flight {
+            if let Err(e) = self.send_payload(id.clone(), payload, true).await {
+                // Identical requests may have started waiting for this one already
+                let waiters = self.response_senders.lock().await.take(&id);
+                if let Some(waiters) = waiters {
+                    waiters.respond(Err(e));
+                }
+            }
+        }
+
+        wait_for_response(receiver, id).await
+    }
+
+    /// Send a request, returning its id so its response can be [received](Self::receive).
+    pub(crate) async fn send<Request>(&self, request: Request) -> Result<String, Error>
+    where
+        Request: Serialize + Send + Debug + 'static,
+    {
+        let payload = serialize_request(request)?;
+        let id = self.next_id();
+
         let (sender, receiver) = oneshot::channel();
-        {
-            self.response_senders
-                .lock()
-                .await
-                .insert(id.clone(), sender);
-            self.response_receivers
-                .lock()
-                .await
-                .insert(id.clone(), receiver);
+        self.response_senders.lock().await.waiting.insert(
+            id.clone(),
+            Waiters {
+                key: None,
+                senders: vec![sender],
+            },
+        );
+        self.response_receivers
+            .lock()
+            .await
+            .insert(id.clone(), receiver);
+
+        if let Err(e) = self.send_payload(id.clone(), payload, true).await {
+            self.response_senders.lock().await.take(&id);
+            self.response_receivers.lock().await.remove(&id);
+            return Err(e);
         }
-        let json_payload = JsonPayload {
+        Ok(id)
+    }
+
+    /// Send a request nobody waits for the response of.
+    pub(crate) async fn notify<Request>(&self, request: Request) -> Result<(), Error>
+    where
+        Request: Serialize + Send + Debug + 'static,
+    {
+        let payload = serialize_request(request)?;
+        let id = self.next_id();
+        self.send_payload(id, payload, false).await
+    }
+
+    /// Send a request nobody waits for the response of, without waiting for room in the queue.
+    ///
+    /// Fails with [`Error::QueueFull`] if the queue is full.
+    pub(crate) fn try_notify<Request>(&self, request: Request) -> Result<(), Error>
+    where
+        Request: Serialize + Send + Debug + 'static,
+    {
+        let payload = serialize_request(request)?;
+        let id = self.next_id();
+        self.sender
+            .try_send(JsonPayload { id, payload })
+            .map_err(|e| self.try_send_error(e))
+    }
+
+    fn try_send_error(&self, e: TrySendError<JsonPayload>) -> Error {
+        match e {
+            TrySendError::Full(_) => Error::QueueFull {
+                capacity: self.sender.capacity().unwrap_or_default(),
+            },
+            TrySendError::Closed(_) => {
+                Error::DenoRuntime("send: couldn't send request, the worker exited".into())
+            }
+        }
+    }
+
+    fn next_id(&self) -> String {
+        self.next_id.fetch_add(1, Ordering::Relaxed).to_string()
+    }
+
+    /// Queue a request for the worker, according to our [`QueuePolicy`].
+    ///
+    /// If someone waits for the response, the span of the request is tracked until it comes in.
+    /// Requests nobody waits for the response of don't track it, and always wait for room in the queue.
+    async fn send_payload(
+        &self,
+        id: String,
+        payload: serde_json::Value,
+        waited_for: bool,
+    ) -> Result<(), Error> {
+        if waited_for {
+            let mut inflight = self.inflight.lock().expect("inflight lock poisoned");
+            inflight.spans.insert(
+                id.clone(),
+                RequestSpan {
+                    span: tracing::Span::current(),
+                    schema_id: payload.get("schemaId").and_then(serde_json::Value::as_u64),
+                },
+            );
+            inflight.queued.insert(id.clone());
+        }
+
+        let request = JsonPayload {
             id: id.clone(),
-            payload: serde_json::to_value(request).map_err(|e| Error::ParameterSerialization {
-                message: format!("deno: couldn't serialize request : `{e:?}`"),
-                name: "request".to_string(),
-            })?,
+            payload,
         };
+        let sent = match self.queue_policy.when_full {
+            QueueFullAction::Reject if waited_for => self
+                .sender
+                .try_send(request)
+                .map_err(|e| self.try_send_error(e)),
+            _ => self
+                .sender
+                .send(request)
+                .await
+                .map_err(|e| Error::DenoRuntime(format!("send: couldn't send request {e}"))),
+        };
+        if sent.is_err() {
+            let mut inflight = self.inflight.lock().expect("inflight lock poisoned");
+            inflight.spans.remove(&id);
+            inflight.queued.remove(&id);
+        }
+        sent
+    }
 
-        self.sender
-            .send(json_payload)
-            .await
-            .map_err(|e| Error::DenoRuntime(format!("send: couldn't send request {e}")))?;
-        Ok(id)
+    /// A snapshot of the request queue of the worker
+    pub(crate) async fn queue_statistics(&self) -> QueueStatistics {
+        QueueStatistics {
+            queued: self.sender.len(),
+            in_flight: self.response_senders.lock().await.waiting.len(),
+            unsent_responses: self.unsent_plans.lock().await.len(),
+        }
     }
 
-    async fn receive<Response>(&self, id: String) -> Result<Response, Error>
+    pub(crate) async fn receive<Response>(&self, id: String) -> Result<Response, Error>
     where
         Response: DeserializeOwned + Send + Debug + 'static,
     {
@@ -196,72 +608,264 @@ impl JsWorker {
             .await
             .remove(&id)
             .expect("couldn't find id in response_receivers");
-        let payload = receiver.await.map_err(|e| {
-            Error::DenoRuntime(format!("request: couldn't receive response: {e:?}"))
-        })?;
+        wait_for_response(receiver, id).await
+    }
 
-        serde_json::from_value(payload).map_err(|e| Error::ParameterDeserialization {
-            message: format!("deno: couldn't deserialize response : `{e:?}`"),
-            id,
-        })
+    /// Stop accepting requests, and wait for the worker to process the queued ones and exit,
+    /// until `deadline`.
+    ///
+    /// Returns whether the javascript runtime exited cleanly in time.
+    /// Otherwise the worker thread keeps running in the background until it exits.
+    pub(crate) async fn shutdown(self, deadline: tokio::time::Instant) -> bool {
+        // The requests already queued can still be received by the worker
+        self.sender.close();
+        matches!(
+            tokio::time::timeout_at(deadline, self.exited).await,
+            Ok(Ok(true))
+        )
+    }
+}
+
+/// A command serialized for a worker, along with the key identical commands are coalesced under.
+pub(crate) struct SerializedRequest {
+    payload: serde_json::Value,
+    key: String,
+}
+
+impl SerializedRequest {
+    pub(crate) fn new<Request: Serialize>(command: Request) -> Result<Self, Error> {
+        let payload = serialize_request(command)?;
+        let key = payload.to_string();
+        Ok(Self { payload, key })
+    }
+
+    /// A hash of the command, which identical commands share
+    pub(crate) fn key_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.key.hash(&mut hasher);
+        hasher.finish()
     }
+}
+
+fn serialize_request<Request: Serialize>(request: Request) -> Result<serde_json::Value, Error> {
+    serde_json::to_value(request).map_err(|e| Error::ParameterSerialization {
+        message: format!("deno: couldn't serialize request : `{e:?}`"),
+        name: "request".to_string(),
+    })
+}
+
+async fn wait_for_response<Response: DeserializeOwned>(
+    receiver: oneshot::Receiver<Result<serde_json::Value, Error>>,
+    id: String,
+) -> Result<Response, Error> {
+    let payload = receiver
+        .await
+        .map_err(|e| Error::DenoRuntime(format!("request: couldn't receive response: {e:?}")))??;
+    deserialize_response(payload, id)
+}
 
-    fn quit(&mut self) -> Result<(), Error> {
-        if let Some(handle) = self.handle.take() {
-            handle.join().map_err(|_| {
-                Error::DenoRuntime("couldn't wait for JsRuntime to finish".to_string())
+fn deserialize_response<Response: DeserializeOwned>(
+    payload: serde_json::Value,
+    id: String,
+) -> Result<Response, Error> {
+    serde_json::from_value(payload).map_err(|e| Error::ParameterDeserialization {
+        message: format!("deno: couldn't deserialize response : `{e:?}`"),
+        id,
+    })
+}
+
+/// Run a javascript worker until its event loop completes.
+///
+/// If the isolate is terminated to interrupt a request, the worker is restarted in the same isolate.
+fn run_js_runtime(
+    worker_source_code: &'static str,
+    my_ext: Extension,
+    heap_policy: HeapPolicy,
+    response_senders: &ResponseSenders,
+    inflight: &std::sync::Mutex<Inflight>,
+    heap_statistics_requests: &mut mpsc::UnboundedReceiver<oneshot::Sender<HeapStatistics>>,
+    heap_expansions: &Arc<AtomicUsize>,
+) -> Result<(), anyhow::Error> {
+    let js = Js::new("query planner".to_string())
+        .with_heap_policy(heap_policy)
+        .with_heap_expansions(heap_expansions.clone());
+    let mut js_runtime = js.build_js_runtime(my_ext);
+    inflight.lock().expect("inflight lock poisoned").isolate =
+        Some(js_runtime.v8_isolate().thread_safe_handle());
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let future = async move {
+        js_runtime.execute_script_static("worker.js", worker_source_code)?;
+        loop {
+            let result = std::future::poll_fn(|cx| {
+                // The isolate can only be inspected from its own thread, in between event loop iterations.
+                while let Poll::Ready(Some(respond_to)) = heap_statistics_requests.poll_recv(cx) {
+                    let statistics = heap_statistics(
+                        js_runtime.v8_isolate(),
+                        heap_expansions.load(Ordering::Relaxed),
+                    );
+                    let _ = respond_to.send(statistics);
+                }
+                js_runtime.poll_event_loop(cx, false)
             })
-        } else {
-            Ok(())
+            .await;
+
+            let heap_limit_reached = js.take_heap_limit_reached();
+            let (terminated, out_of_memory) = {
+                let mut inflight = inflight.lock().expect("inflight lock poisoned");
+                let terminated = inflight.terminated.take();
+                let mut out_of_memory = None;
+                if heap_limit_reached {
+                    // The request the worker was processing is the one that exhausted the heap.
+                    out_of_memory = inflight.current.take();
+                    if let Some(request) = &out_of_memory {
+                        inflight.spans.remove(&request.id);
+                    }
+                } else if let Some(terminated) = &terminated {
+                    // The isolate might have been terminated right after the request we wanted to
+                    // interrupt completed. The request it was working on then needs to be handed back.
+                    if let Some(current) = inflight.current.take() {
+                        if &current.id != terminated {
+                            inflight.replay.push_front(current);
+                        }
+                    }
+                }
+                (terminated, out_of_memory)
+            };
+
+            match result {
+                Err(e) if heap_limit_reached || terminated.is_some() => {
+                    if heap_limit_reached {
+                        tracing::error!(
+                            "jsworker: a request exhausted the heap ({e}), restarting the worker"
+                        );
+                        if let Some(request) = out_of_memory {
+                            let waiters = response_senders.lock().await.take(&request.id);
+                            if let Some(waiters) = waiters {
+                                waiters.respond(Err(js.heap_limit_exceeded()));
+                            }
+                        }
+                        js.reset_heap_limit(&mut js_runtime);
+                    } else if let Some(id) = terminated {
+                        tracing::warn!(
+                            "jsworker: request {id} was interrupted ({e}), restarting the worker"
+                        );
+                    }
+                    // Global state such as the loaded schemas survives the termination,
+                    // only the worker's `run` loop needs to be started again.
+                    js_runtime.v8_isolate().cancel_terminate_execution();
+                    js_runtime.execute_script_static("<restart>", "run();")?;
+                }
+                result => return result,
+            }
         }
-    }
+    };
+    runtime.block_on(future)
 }
 
-impl Drop for JsWorker {
-    fn drop(&mut self) {
-        self.quit().unwrap_or_else(|e| eprintln!("{e}"));
-    }
+/// The structured fields of a log emitted by the javascript worker
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct LogFields {
+    operation_name: Option<String>,
+    /// What the worker was doing, such as planning
+    phase: Option<String>,
+}
+
+/// Run `f` in the span of the request the javascript worker is processing,
+/// with the id of the schema the request is for
+fn in_request_span(state: &OpState, f: impl FnOnce(Option<u64>)) {
+    let RequestSpan { span, schema_id } = state
+        .borrow::<Arc<std::sync::Mutex<Inflight>>>()
+        .lock()
+        .expect("inflight lock poisoned")
+        .current_span();
+    span.in_scope(|| f(schema_id))
+}
+
+/// Emit a log of the javascript worker, along with its structured fields
+macro_rules! js_log {
+    ($level:expr, $message:expr, $schema_id:expr, $fields:expr) => {{
+        let schema_id: Option<u64> = $schema_id;
+        let LogFields {
+            operation_name,
+            phase,
+        } = $fields;
+        tracing::event!(
+            $level,
+            schema_id,
+            operation_name = operation_name.as_deref(),
+            phase = phase.as_deref(),
+            "{}",
+            $message
+        )
+    }};
 }
 
 // Logging capabilities
 #[op]
-fn log_trace(_: &mut OpState, message: String) -> Result<(), anyhow::Error> {
-    tracing::trace!("{message}");
+fn log_trace(state: &mut OpState, message: String, fields: LogFields) -> Result<(), anyhow::Error> {
+    in_request_span(state, |schema_id| {
+        js_log!(tracing::Level::TRACE, message, schema_id, fields)
+    });
     Ok(())
 }
 
 #[op]
-fn log_debug(_: &mut OpState, message: String) -> Result<(), anyhow::Error> {
-    tracing::debug!("{message}");
+fn log_debug(state: &mut OpState, message: String, fields: LogFields) -> Result<(), anyhow::Error> {
+    in_request_span(state, |schema_id| {
+        js_log!(tracing::Level::DEBUG, message, schema_id, fields)
+    });
     Ok(())
 }
 
 #[op]
-fn log_info(_: &mut OpState, message: String) -> Result<(), anyhow::Error> {
-    tracing::info!("{message}");
+fn log_info(state: &mut OpState, message: String, fields: LogFields) -> Result<(), anyhow::Error> {
+    in_request_span(state, |schema_id| {
+        js_log!(tracing::Level::INFO, message, schema_id, fields)
+    });
     Ok(())
 }
 
 #[op]
-fn log_warn(_: &mut OpState, message: String) -> Result<(), anyhow::Error> {
-    tracing::warn!("{message}");
+fn log_warn(state: &mut OpState, message: String, fields: LogFields) -> Result<(), anyhow::Error> {
+    in_request_span(state, |schema_id| {
+        js_log!(tracing::Level::WARN, message, schema_id, fields)
+    });
     Ok(())
 }
 
 #[op]
-fn log_error(_: &mut OpState, message: String) -> Result<(), anyhow::Error> {
-    tracing::error!("{message}");
+fn log_error(state: &mut OpState, message: String, fields: LogFields) -> Result<(), anyhow::Error> {
+    in_request_span(state, |schema_id| {
+        js_log!(tracing::Level::ERROR, message, schema_id, fields)
+    });
     Ok(())
 }
 
 #[op]
 async fn send(state: Rc<RefCell<OpState>>, payload: JsonPayload) -> Result<(), anyhow::Error> {
-    let sender = {
+    let (sender, inflight) = {
         let state = state.borrow();
         // we're cloning here because we don't wanna keep the borrow across an await point
-        state.borrow::<Sender<JsonPayload>>().clone()
+        (
+            state.borrow::<Sender<JsonPayload>>().clone(),
+            state.borrow::<Arc<std::sync::Mutex<Inflight>>>().clone(),
+        )
     };
 
+    {
+        let mut inflight = inflight.lock().expect("inflight lock poisoned");
+        if inflight.current.as_ref().map(|current| &current.id) == Some(&payload.id) {
+            inflight.current = None;
+        }
+        inflight.spans.remove(&payload.id);
+        inflight.cancelled.remove(&payload.id);
+    }
+
     sender
         .send(payload)
         .await
@@ -270,15 +874,36 @@ async fn send(state: Rc<RefCell<OpState>>, payload: JsonPayload) -> Result<(), a
 
 #[op]
 async fn receive(state: Rc<RefCell<OpState>>) -> Result<JsonPayload, anyhow::Error> {
-    let receiver = {
+    let (receiver, inflight) = {
         let state = state.borrow();
-        state.borrow::<Receiver<JsonPayload>>().clone()
+        (
+            state.borrow::<Receiver<JsonPayload>>().clone(),
+            state.borrow::<Arc<std::sync::Mutex<Inflight>>>().clone(),
+        )
     };
 
-    receiver
-        .recv()
-        .await
-        .map_err(|e| anyhow::anyhow!("op_receive: couldn't send response {e}"))
+    loop {
+        let replay = inflight
+            .lock()
+            .expect("inflight lock poisoned")
+            .replay
+            .pop_front();
+        let payload = match replay {
+            Some(payload) => payload,
+            None => receiver
+                .recv()
+                .await
+                .map_err(|e| anyhow::anyhow!("op_receive: couldn't receive request {e}"))?,
+        };
+
+        let mut inflight = inflight.lock().expect("inflight lock poisoned");
+        inflight.queued.remove(&payload.id);
+        if inflight.cancelled.remove(&payload.id) {
+            continue;
+        }
+        inflight.current = Some(payload.clone());
+        return Ok(payload);
+    }
 }
 
 // function presence tested in router-bridge/js-src/test_get_random_values.ts
@@ -302,20 +927,36 @@ fn op_crypto_get_random_values(state: &mut OpState, out: &mut [u8]) -> Result<()
     Ok(())
 }
 
+// Neither `performance.now` nor a high resolution `Date.now` are available to the workers,
+// so planning phases are timed with this clock.
+static CLOCK_START: OnceLock<Instant> = OnceLock::new();
+
+/// The milliseconds elapsed on a monotonic clock, with a microsecond resolution.
+#[op]
+fn monotonic_now() -> f64 {
+    CLOCK_START.get_or_init(Instant::now).elapsed().as_micros() as f64 / 1000.0
+}
+
 #[cfg(test)]
 mod worker_tests {
     use super::JsWorker;
+    use crate::error::Error;
+    use crate::heap::{HeapLimitAction, HeapPolicy};
     use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+    use tokio::time::Instant;
+    use tracing::Instrument;
 
     #[tokio::test]
     #[tracing_test::traced_test]
     async fn logging_works() {
+        // logs are emitted in the span of the request, which is the test's
         let expected_present_logs = [
-            "TRACE router_bridge::worker: this is a Trace level log",
-            "DEBUG router_bridge::worker: this is a Debug level log",
-            "INFO router_bridge::worker: this is an Info level log",
-            "WARN router_bridge::worker: this is a Warn level log",
-            "ERROR router_bridge::worker: this is an Error level log",
+            "TRACE logging_works: router_bridge::worker: this is a Trace level log",
+            "DEBUG logging_works: router_bridge::worker: this is a Debug level log",
+            "INFO logging_works: router_bridge::worker: this is an Info level log",
+            "WARN logging_works: router_bridge::worker: this is a Warn level log",
+            "ERROR logging_works: router_bridge::worker: this is an Error level log",
         ];
         run_logger().await;
         logs_assert(|lines: &[&str]| {
@@ -332,23 +973,214 @@ mod worker_tests {
         });
     }
 
-    async fn run_logger() {
-        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
-        enum Kind {
-            Trace,
-            Debug,
-            Info,
-            Warn,
-            Error,
-            Exit,
-        }
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn identical_requests_are_coalesced() {
+        let worker = JsWorker::new(
+            include_str!("../bundled/test_logger_worker.js"),
+            Default::default(),
+            Default::default(),
+        );
+        let command = || Command {
+            kind: Kind::Info,
+            message: Some("this request is coalesced".to_string()),
+        };
 
-        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
-        struct Command {
-            kind: Kind,
-            message: Option<String>,
-        }
-        let worker = JsWorker::new(include_str!("../bundled/test_logger_worker.js"));
+        let (first, second): (Result<bool, Error>, Result<bool, Error>) =
+            tokio::join!(worker.request(command()), worker.request(command()));
+        assert!(first.unwrap());
+        assert!(second.unwrap());
+
+        // once the response is in, the same command is sent again
+        let third: bool = worker.request(command()).await.unwrap();
+        assert!(third);
+
+        let shutdown_succeeded: bool = worker
+            .request(Command {
+                kind: Kind::Exit,
+                message: None,
+            })
+            .await
+            .unwrap();
+        assert!(shutdown_succeeded, "couldn't send shutdown command");
+
+        // the worker processed the first two requests once, and the third one
+        logs_assert(|lines: &[&str]| {
+            let processed = lines
+                .iter()
+                .filter(|line| line.ends_with("this request is coalesced"))
+                .count();
+            assert_eq!(2, processed, "{}", lines.join("\n"));
+            Ok(())
+        });
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn structured_logging() {
+        let worker = JsWorker::new(
+            include_str!("../bundled/test_logger_worker.js"),
+            Default::default(),
+            Default::default(),
+        );
+
+        // the schema id doesn't fit in a javascript number
+        let logged: bool = worker
+            .request(StructuredCommand {
+                kind: Kind::Structured,
+                message: "this is a structured log".to_string(),
+                schema_id: u64::MAX - 1,
+            })
+            .instrument(tracing::info_span!("plan", request = 42))
+            .await
+            .unwrap();
+        assert!(logged, "couldn't send structured log command");
+
+        let shutdown_succeeded: bool = worker
+            .request(Command {
+                kind: Kind::Exit,
+                message: None,
+            })
+            .await
+            .unwrap();
+        assert!(shutdown_succeeded, "couldn't send shutdown command");
+
+        let expected = "INFO structured_logging:plan{request=42}: router_bridge::worker: \
+            this is a structured log schema_id=18446744073709551614 operation_name=\"Me\" phase=\"Plan\"";
+        logs_assert(|lines: &[&str]| {
+            assert!(
+                lines.iter().any(|line| line.ends_with(expected)),
+                "couldn't find log `{}` in the traced logs:\n{}",
+                expected,
+                lines.join("\n")
+            );
+            Ok(())
+        });
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+    enum Kind {
+        Trace,
+        Debug,
+        Info,
+        Warn,
+        Error,
+        Structured,
+        Exit,
+        Crash,
+        Allocate,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+    struct Command {
+        kind: Kind,
+        message: Option<String>,
+    }
+
+    #[derive(Serialize, Debug)]
+    #[serde(rename_all = "camelCase")]
+    struct StructuredCommand {
+        kind: Kind,
+        message: String,
+        schema_id: u64,
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn worker_is_respawned_after_a_crash() {
+        let worker = JsWorker::new(
+            include_str!("../bundled/test_logger_worker.js"),
+            Default::default(),
+            Default::default(),
+        );
+        worker
+            .set_restart_command(
+                "state".to_string(),
+                Command {
+                    kind: Kind::Info,
+                    message: Some("this state was replayed".to_string()),
+                },
+            )
+            .unwrap();
+
+        let crashed: Result<bool, _> = worker
+            .request(Command {
+                kind: Kind::Crash,
+                message: Some("boom".to_string()),
+            })
+            .await;
+        assert!(crashed.is_err(), "the crashing request should fail");
+
+        let trace_succeeded: bool = worker
+            .request(Command {
+                kind: Kind::Trace,
+                message: Some("still alive".to_string()),
+            })
+            .await
+            .unwrap();
+        let shutdown_succeeded: bool = worker
+            .request(Command {
+                kind: Kind::Exit,
+                message: None,
+            })
+            .await
+            .unwrap();
+        assert!(trace_succeeded, "couldn't send trace log command");
+        assert!(shutdown_succeeded, "couldn't send shutdown command");
+
+        assert!(logs_contain("this state was replayed"));
+        assert!(logs_contain("still alive"));
+    }
+
+    #[tokio::test]
+    async fn heap_limit_fails_the_request() {
+        let worker = JsWorker::new(
+            include_str!("../bundled/test_logger_worker.js"),
+            HeapPolicy {
+                initial_limit_mb: 32,
+                growth_factor: 1.5,
+                max_limit_mb: Some(64),
+                on_max_limit: HeapLimitAction::Fail,
+            },
+            Default::default(),
+        );
+
+        let allocated: Result<bool, _> = worker
+            .request(Command {
+                kind: Kind::Allocate,
+                message: None,
+            })
+            .await;
+        assert!(
+            matches!(allocated, Err(Error::HeapLimitExceeded { limit_mb: 64 })),
+            "{allocated:?}"
+        );
+
+        // the worker is still usable
+        let trace_succeeded: bool = worker
+            .request(Command {
+                kind: Kind::Trace,
+                message: Some("still alive".to_string()),
+            })
+            .await
+            .unwrap();
+        let shutdown_succeeded: bool = worker
+            .request(Command {
+                kind: Kind::Exit,
+                message: None,
+            })
+            .await
+            .unwrap();
+        assert!(trace_succeeded, "couldn't send trace log command");
+        assert!(shutdown_succeeded, "couldn't send shutdown command");
+    }
+
+    async fn run_logger() {
+        let worker = JsWorker::new(
+            include_str!("../bundled/test_logger_worker.js"),
+            Default::default(),
+            Default::default(),
+        );
 
         let trace_succeeded: bool = worker
             .request(Command {
@@ -408,22 +1240,67 @@ mod worker_tests {
         assert!(shutdown_succeeded, "couldn't send shutdown command");
     }
 
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn shutdown_drains_queued_requests() {
+        let worker = JsWorker::new(
+            include_str!("../bundled/test_logger_worker.js"),
+            Default::default(),
+            Default::default(),
+        );
+        // nobody waits for the response, but the worker still processes the request before exiting
+        worker
+            .send(Command {
+                kind: Kind::Info,
+                message: Some("processed before exiting".to_string()),
+            })
+            .await
+            .unwrap();
+
+        assert!(
+            worker
+                .shutdown(Instant::now() + Duration::from_secs(10))
+                .await
+        );
+        assert!(logs_contain("processed before exiting"));
+    }
+
     #[tokio::test]
     // This test ensures crypto.getRandomValues can be called.
     // the uuid dependency relies on it since v9.0
     async fn test_get_random_values() {
-        let mut worker = JsWorker::new(include_str!("../bundled/test_get_random_values.js"));
+        let worker = JsWorker::new(
+            include_str!("../bundled/test_get_random_values.js"),
+            Default::default(),
+            Default::default(),
+        );
 
-        worker.quit().unwrap();
+        assert!(
+            worker
+                .shutdown(Instant::now() + Duration::from_secs(10))
+                .await
+        );
     }
 
     #[tokio::test]
     // This test ensures the URL api is available.
     // federation relies on it since 2.7
     async fn test_url() {
-        let mut worker = JsWorker::new(include_str!("../bundled/test_get_random_values.js"));
+        let worker = JsWorker::new(
+            include_str!("../bundled/test_get_random_values.js"),
+            Default::default(),
+            Default::default(),
+        );
 
-        JsWorker::new(include_str!("../bundled/test_url.js"));
-        worker.quit().unwrap();
+        JsWorker::new(
+            include_str!("../bundled/test_url.js"),
+            Default::default(),
+            Default::default(),
+        );
+        assert!(
+            worker
+                .shutdown(Instant::now() + Duration::from_secs(10))
+                .await
+        );
     }
 }